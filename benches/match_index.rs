@@ -0,0 +1,286 @@
+//! Compares the indexed matchers against the ones of the baseline, which
+//! scanned the whole database.
+//!
+//! Run with `cargo bench --bench match_index`, the number of the generated
+//! entries can be changed with `FASTJUMP_BENCH_ENTRIES`.
+use fastjump::common::utils::find_matches;
+use fastjump::database::Database;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::time::{Duration, Instant};
+
+const WORDS: &[&str] = &[
+    "src",
+    "Projects",
+    "build",
+    "target",
+    "release",
+    "docs",
+    "home",
+    "user",
+    "lib",
+    "include",
+    "Cargo",
+    "node_modules",
+    "vendor",
+    "cache",
+    "tmp",
+    "Work",
+    "assets",
+    "config",
+    "tests",
+    "scripts",
+];
+const NEEDLES: &[&[&str]] = &[&["proj", "rel"], &["node"], &["Cargo"], &["confg"]];
+
+/// A deterministic pseudo-random path generator.
+fn generate(count: usize) -> Database {
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    let mut data = HashMap::with_capacity(count);
+    while data.len() < count {
+        let depth = 2 + next() % 6;
+        let mut path = PathBuf::from(MAIN_SEPARATOR.to_string());
+        for _ in 0..depth {
+            let word = WORDS[(next() % WORDS.len() as u64) as usize];
+            path.push(format!("{}{}", word, next() % 100));
+        }
+        data.insert(path, (next() % 1000) as f32 / 10.0);
+    }
+    Database::from(data)
+}
+
+/// The matchers of `find_matches` as they were before the index, copied
+/// as they were but for the logging, which is disabled anyway.
+mod baseline {
+    use std::borrow::Cow;
+    use std::cmp::Ordering;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+    use strsim::normalized_levenshtein;
+
+    const ENTRIES_COUNT: usize = 9;
+    const FUZZY_MATCH_THRESHOLD: f64 = 0.6;
+
+    fn make_ascii_lowercase_cow(s: &mut Cow<'_, str>) {
+        if s.chars().any(|x| x.is_ascii_uppercase()) {
+            s.to_mut().make_ascii_lowercase();
+        }
+    }
+
+    fn match_anywhere<'a>(
+        needles: &[&Path],
+        data: &'a HashMap<PathBuf, f32>,
+        ignore_case: bool,
+    ) -> Vec<(&'a Path, f32)> {
+        let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
+
+        for (k, v) in data.iter() {
+            let mut path = k.to_string_lossy();
+            make_ascii_lowercase_cow(&mut path);
+
+            let contains = &mut |_, needle: &&Path| {
+                if ignore_case {
+                    let mut needle = needle.to_string_lossy();
+                    make_ascii_lowercase_cow(&mut needle);
+                    if !path.contains(needle.as_ref()) {
+                        return None;
+                    }
+                } else if !path.contains(needle.to_string_lossy().as_ref()) {
+                    return None;
+                }
+                Some(())
+            };
+            if needles.iter().try_fold((), contains).is_some() {
+                candidates.push((k, *v));
+            }
+        }
+        candidates
+    }
+
+    fn match_consecutive<'a>(
+        needles: &[&Path],
+        data: &'a HashMap<PathBuf, f32>,
+        ignore_case: bool,
+    ) -> Vec<(&'a Path, f32)> {
+        let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
+
+        for (k, v) in data.iter() {
+            let path = k.to_string_lossy();
+            let mut part_iter = path.split(MAIN_SEPARATOR).rev();
+            if needles
+                .iter()
+                .rev()
+                .try_fold((), |_, needle| {
+                    if let Some(part) = part_iter.next() {
+                        if ignore_case {
+                            let mut part = Cow::from(part);
+                            make_ascii_lowercase_cow(&mut part);
+                            let mut needle = needle.to_string_lossy();
+                            make_ascii_lowercase_cow(&mut needle);
+
+                            if !part.contains(needle.as_ref()) {
+                                return None;
+                            }
+                        } else if !part.contains(needle.to_string_lossy().as_ref()) {
+                            return None;
+                        }
+                    } else {
+                        return None;
+                    }
+                    Some(())
+                })
+                .is_some()
+            {
+                candidates.push((k, *v));
+            }
+        }
+        candidates
+    }
+
+    fn match_fuzzy<'a>(
+        needles: &[&Path],
+        data: &'a HashMap<PathBuf, f32>,
+        ignore_case: bool,
+    ) -> Vec<(&'a Path, f32)> {
+        let end_dir = |path: &'a Path| -> Cow<'a, str> {
+            path.file_name()
+                .expect("expect a non-empty path")
+                .to_string_lossy()
+        };
+        let mut needle = needles
+            .last()
+            .expect("Expect a non-empty path to search")
+            .to_string_lossy();
+        make_ascii_lowercase_cow(&mut needle);
+        let mut match_percent: Box<dyn FnMut(&'a Path) -> f64> = if ignore_case {
+            Box::new(|path: &Path| {
+                let mut end = end_dir(path);
+                make_ascii_lowercase_cow(&mut end);
+                normalized_levenshtein(needle.as_ref(), end.as_ref())
+            })
+        } else {
+            Box::new(|path: &Path| normalized_levenshtein(needle.as_ref(), end_dir(path).as_ref()))
+        };
+        let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
+
+        for (k, v) in data.iter() {
+            if match_percent(k) >= FUZZY_MATCH_THRESHOLD {
+                candidates.push((k, *v));
+            }
+        }
+        candidates
+    }
+
+    pub fn find_matches<'a>(
+        data: &'a HashMap<PathBuf, f32>,
+        cwd: &Path,
+        needles: &[&Path],
+    ) -> Vec<(&'a Path, f32)> {
+        let ignore_case = !needles
+            .iter()
+            .any(|s| s.to_string_lossy().chars().any(|c| c.is_ascii_uppercase()));
+        let sort = |a: &(&'a Path, f32), b: &(&'a Path, f32)| {
+            let weight =
+                b.1.partial_cmp(&a.1)
+                    .expect("can't compare the two float numbers");
+            if weight == Ordering::Equal {
+                b.0.cmp(a.0)
+            } else {
+                weight
+            }
+        };
+
+        let mut match_1 = match_consecutive(needles, data, ignore_case);
+        let mut match_2 = match_fuzzy(needles, data, ignore_case);
+        let mut match_3 = match_anywhere(needles, data, ignore_case);
+
+        match_1.sort_unstable_by(sort);
+        match_2.sort_unstable_by(sort);
+        match_3.sort_unstable_by(sort);
+
+        match_1
+            .into_iter()
+            .chain(match_2)
+            .chain(match_3)
+            .filter(|(path, _)| *path != cwd)
+            .collect()
+    }
+}
+
+fn baseline_query(data: &Database, needles: &[&str]) -> usize {
+    let needles: Vec<_> = needles.iter().map(Path::new).collect();
+    baseline::find_matches(data, Path::new("/"), &needles).len()
+}
+
+fn query(data: &Database, needles: &[&str]) -> usize {
+    let needles: Vec<_> = needles.iter().map(Path::new).collect();
+    find_matches(data, Path::new("/"), &needles, None).len()
+}
+
+fn measure(name: &str, rounds: u32, mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..rounds {
+        f();
+    }
+    let elapsed = start.elapsed() / rounds;
+    println!("{:<32}{:>12.3?}", name, elapsed);
+    elapsed
+}
+
+fn main() {
+    let count = std::env::var("FASTJUMP_BENCH_ENTRIES")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(100_000);
+    let rounds = 5;
+    let data = generate(count);
+    println!("{} entries, {} rounds per query\n", count, rounds);
+
+    for needles in NEEDLES {
+        println!("needles {:?}", needles);
+        // the baseline lowercased the paths for the anywhere matcher even if
+        // the needles were case sensitive, so the counts can differ
+        println!(
+            "  {} matches, {} by the baseline",
+            query(&data, needles),
+            baseline_query(&data, needles)
+        );
+        let baseline = measure("  baseline matchers", rounds, || {
+            std::hint::black_box(baseline_query(&data, needles));
+        });
+        let mut copies = (0..rounds)
+            .map(|_| Database::from(data.clone()))
+            .collect::<Vec<_>>()
+            .into_iter();
+        // keep the databases alive to not to measure dropping them
+        let mut used = Vec::with_capacity(rounds as usize);
+        let cold = measure("  index build + query", rounds, || {
+            let data = copies.next().unwrap();
+            std::hint::black_box(query(&data, needles));
+            used.push(data);
+        });
+        let data = Database::from(data.clone());
+        data.index();
+        let warm = measure("  query on built index", rounds, || {
+            std::hint::black_box(query(&data, needles));
+        });
+        // as the daemon and `Jumper` hold it
+        let mut data = data;
+        data.keep_index();
+        let trigram = measure("  query on trigram table", rounds, || {
+            std::hint::black_box(query(&data, needles));
+        });
+        println!(
+            "  speedup: {:.1}x cold, {:.1}x warm, {:.1}x trigram\n",
+            baseline.as_secs_f64() / cold.as_secs_f64(),
+            baseline.as_secs_f64() / warm.as_secs_f64(),
+            baseline.as_secs_f64() / trigram.as_secs_f64()
+        );
+    }
+}
@@ -24,12 +24,9 @@ fi
 _fastjump() {
         local cur
         cur=${COMP_WORDS[*]:1}
-        comps=$(fastjump --complete $cur)
-        while read i; do
+        while IFS= read -r -d '' i; do
             COMPREPLY=("${COMPREPLY[@]}" "${i}")
-        done <<EOF
-        $comps
-EOF
+        done < <(fastjump --complete --print0 $cur)
 }
 complete -F _fastjump j
 
@@ -43,18 +40,6 @@ fastjump_add_to_database() {
     fi
 }
 
-while IFS= read -r line; do
-    IFS=' '
-    read -ra fields <<<$line
-    if [[ ${fields[1]} =~ ^cd$ && -e ${fields[2]} ]]; then
-        if [[ -f "${FASTJUMP_ERROR_PATH}" ]]; then
-            (fastjump --add ${fields[2]} >/dev/null 2>>${FASTJUMP_ERROR_PATH} &) &>/dev/null
-        else
-            (fastjump --add ${fields[2]} >/dev/null &) &>/dev/null
-        fi
-    fi
-done <<< "$(history)"
-
 case $PROMPT_COMMAND in
     *fastjump*)
         ;;
@@ -71,7 +56,8 @@ j() {
         return
     fi
 
-    output="$(fastjump ${@})"
+    local output
+    IFS= read -r -d '' output < <(fastjump --print0 ${@})
     if [[ -d "${output}" ]]; then
         if [ -t 1 ]; then  # if stdout is a terminal, use colors
                 echo -e "\\033[31m${output}\\033[0m"
@@ -106,7 +92,8 @@ jo() {
         return
     fi
 
-    output="$(fastjump ${@})"
+    local output
+    IFS= read -r -d '' output < <(fastjump --print0 ${@})
     if [[ -d "${output}" ]]; then
         case ${OSTYPE} in
             linux*)
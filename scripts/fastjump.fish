@@ -12,7 +12,7 @@ end
 
 
 # enable tab completion
-complete -x -c j -a '(fastjump --complete (commandline -t))'
+complete -x -c j -a '(fastjump --complete --complete-format fish --print0 (commandline -t) | string split0)'
 
 
 # set error file location
@@ -48,7 +48,7 @@ function j
         case '-*' '--*'
             fastjump $argv
         case '*'
-            set -l output (fastjump $argv)
+            set -l output (fastjump --print0 $argv | string split0)
             # Check for . and attempt a regular cd
             if [ $output = "." ]
                 cd $argv
@@ -81,13 +81,13 @@ end
 
 # open fastjump results in file browser
 function jo
-    set -l output (fastjump $argv)
+    set -l output (fastjump --print0 $argv | string split0)
     if test -d "$output"
         switch $OSTYPE
             case 'linux*'
-                xdg-open (fastjump $argv)
+                xdg-open $output
             case 'darwin*'
-                open (fastjump $argv)
+                open $output
             case cygwin
                 cygstart "" (cygpath -w -a (pwd))
             case '*'
@@ -9,7 +9,7 @@ end
 clink.prompt.register_filter(fastjump_add_to_database, 99)
 
 function fastjump_completion(word)
-  for line in io.popen("\"" .. FASTJUMP_BIN .. "\"" ..  " --complete " .. word):lines() do
+  for line in io.popen("\"" .. FASTJUMP_BIN .. "\"" ..  " --complete -- " .. word):lines() do
     clink.add_match(line)
   end
   return {}
@@ -8,19 +8,33 @@ elif [ -n "${__fish_datadir}" ]; then
     shell="fish"
 elif [ -n "${version}" ]; then
     shell="tcsh"
+elif [ -n "${KSH_VERSION}" ]; then
+    shell="posix"
 else
-    shell=$(echo ${SHELL} | awk -F/ '{ print $NF }')
+    # the sourcing shell is in $0 for the interactive ones, eg. -dash
+    case ${0##*/} in
+        sh|-sh|dash|-dash|ash|-ash|ksh*|-ksh*|mksh|-mksh)
+            shell="posix"
+            ;;
+        *)
+            shell=$(echo ${SHELL} | awk -F/ '{ print $NF }')
+            ;;
+    esac
 fi
 
-# prevent circular loop for sh shells
-if [ "${shell}" = "sh" ]; then
-    return 0
+# sh, dash, ash and ksh share the POSIX integration, which also prevents a
+# circular loop for sh shells
+case ${shell} in
+    sh|dash|ash|ksh|mksh)
+        shell="posix"
+        ;;
+esac
 
 # check local install
-elif [ -s ~/.fastjump/share/fastjump/fastjump.${shell} ]; then
-    source ~/.fastjump/share/fastjump/fastjump.${shell}
+if [ -s ~/.fastjump/share/fastjump/fastjump.${shell} ]; then
+    . ~/.fastjump/share/fastjump/fastjump.${shell}
 
 # check global install
 elif [ -s /usr/local/share/fastjump/fastjump.${shell} ]; then
-    source /usr/local/share/fastjump/fastjump.${shell}
+    . /usr/local/share/fastjump/fastjump.${shell}
 fi
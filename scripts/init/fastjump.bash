@@ -0,0 +1,140 @@
+# fastjump integration for bash, generated by `fastjump init bash`
+#
+# add the following line to ~/.bashrc:
+#
+#     eval "$(fastjump init bash)"
+
+export FASTJUMP_SOURCED=1
+
+# set error file location
+if [[ "$(uname)" == "Darwin" ]]; then
+    export FASTJUMP_ERROR_PATH=~/Library/fastjump/errors.log
+elif [[ -n "${XDG_DATA_HOME}" ]]; then
+    export FASTJUMP_ERROR_PATH="${XDG_DATA_HOME}/fastjump/errors.log"
+else
+    export FASTJUMP_ERROR_PATH=~/.local/share/fastjump/errors.log
+fi
+
+if [[ ! -d "$(dirname "${FASTJUMP_ERROR_PATH}")" ]]; then
+    mkdir -p "$(dirname "${FASTJUMP_ERROR_PATH}")"
+fi
+
+
+# enable tab completion
+__fastjump_complete() {
+    local cur
+    cur=${COMP_WORDS[*]:1}
+    COMPREPLY=()
+    while IFS= read -r -d '' i; do
+        COMPREPLY+=("${i}")
+    done < <(fastjump --complete --print0 -- ${cur})
+}
+complete -F __fastjump_complete {{cmd}}
+
+
+# record the current directory
+__fastjump_add() {
+    (fastjump --add "${PWD}" >/dev/null 2>>"${FASTJUMP_ERROR_PATH}" &) &>/dev/null
+}
+
+{{#prompt}}
+# on every prompt
+__fastjump_hook() {
+    __fastjump_add
+}
+{{/prompt}}
+{{#pwd}}
+# on directory changes, bash has no hook for them so check on every prompt
+__fastjump_hook() {
+    if [[ "${__fastjump_oldpwd}" != "${PWD}" ]]; then
+        __fastjump_oldpwd="${PWD}"
+        __fastjump_add
+    fi
+}
+{{/pwd}}
+
+case ${PROMPT_COMMAND} in
+    *__fastjump_hook*)
+        ;;
+    *)
+        PROMPT_COMMAND="${PROMPT_COMMAND:+${PROMPT_COMMAND%;} ; }__fastjump_hook"
+        ;;
+esac
+
+
+# default fastjump command
+{{cmd}}() {
+    if [[ ${1} == -* ]] && [[ ${1} != "--" ]]; then
+        fastjump "$@"
+        return
+    fi
+    [[ ${1} == "--" ]] && shift
+
+    local output
+    IFS= read -r -d '' output < <(fastjump --print0 -- "$@")
+    if [[ -d "${output}" ]]; then
+        if [ -t 1 ]; then  # if stdout is a terminal, use colors
+            printf '\033[31m%s\033[0m\n' "${output}"
+        else
+            printf '%s\n' "${output}"
+        fi
+        cd "${output}"
+    else
+        echo "fastjump: directory '$*' not found"
+        echo "Try \`fastjump --help\` for more information."
+        false
+    fi
+}
+
+
+# jump to child directory (subdirectory of current path)
+{{cmd}}c() {
+    if [[ ${1} == -* ]] && [[ ${1} != "--" ]]; then
+        fastjump "$@"
+    else
+        {{cmd}} "${PWD}" "$@"
+    fi
+}
+
+
+# open fastjump results in file browser
+{{cmd}}o() {
+    if [[ ${1} == -* ]] && [[ ${1} != "--" ]]; then
+        fastjump "$@"
+        return
+    fi
+    [[ ${1} == "--" ]] && shift
+
+    local output
+    IFS= read -r -d '' output < <(fastjump --print0 -- "$@")
+    if [[ -d "${output}" ]]; then
+        case ${OSTYPE} in
+            linux*)
+                xdg-open "${output}"
+                ;;
+            darwin*)
+                open "${output}"
+                ;;
+            cygwin)
+                cygstart "" "$(cygpath -w -a "${output}")"
+                ;;
+            *)
+                echo "Unknown operating system: ${OSTYPE}." 1>&2
+                ;;
+        esac
+    else
+        echo "fastjump: directory '$*' not found"
+        echo "Try \`fastjump --help\` for more information."
+        false
+    fi
+}
+
+
+# open fastjump results (child directory) in file browser
+{{cmd}}co() {
+    if [[ ${1} == -* ]] && [[ ${1} != "--" ]]; then
+        fastjump "$@"
+    else
+        {{cmd}}o "${PWD}" "$@"
+    fi
+}
@@ -0,0 +1,123 @@
+# fastjump integration for fish, generated by `fastjump init fish`
+#
+# add the following line to ~/.config/fish/config.fish:
+#
+#     fastjump init fish | source
+
+set -gx FASTJUMP_SOURCED 1
+
+# Set ostype, if not set
+if not set -q OSTYPE
+    set -gx OSTYPE (bash -c 'echo ${OSTYPE}')
+end
+
+
+# set error file location
+if test (uname) = "Darwin"
+    set -gx FASTJUMP_ERROR_PATH ~/Library/fastjump/errors.log
+else if test -d "$XDG_DATA_HOME"
+    set -gx FASTJUMP_ERROR_PATH $XDG_DATA_HOME/fastjump/errors.log
+else
+    set -gx FASTJUMP_ERROR_PATH ~/.local/share/fastjump/errors.log
+end
+
+if test ! -d (dirname $FASTJUMP_ERROR_PATH)
+    mkdir -p (dirname $FASTJUMP_ERROR_PATH)
+end
+
+
+# enable tab completion
+complete -x -c {{cmd}} -a '(fastjump --complete --complete-format fish --print0 -- (commandline -t) | string split0)'
+
+
+# record the current directory
+{{#prompt}}
+# on every prompt
+function __fastjump_hook --on-event fish_prompt
+{{/prompt}}
+{{#pwd}}
+# on directory changes
+function __fastjump_hook --on-variable PWD
+{{/pwd}}
+    status --is-command-substitution; and return
+    fastjump --add $PWD >/dev/null 2>>$FASTJUMP_ERROR_PATH &
+end
+
+
+# misc helper functions
+function __fastjump_err
+    echo -e $argv 1>&2; false
+end
+
+# default fastjump command
+function {{cmd}}
+    switch "$argv[1]"
+        case '--'
+            set -e argv[1]
+        case '-*'
+            fastjump $argv
+            return
+    end
+
+    set -l output (fastjump --print0 -- $argv | string split0)
+    if test -d "$output"
+        set_color red
+        echo $output
+        set_color normal
+        cd $output
+    else
+        __fastjump_err "fastjump: directory '"$argv"' not found"
+        __fastjump_err "Try `fastjump --help` for more information."
+    end
+end
+
+
+# jump to child directory (subdirectory of current path)
+function {{cmd}}c
+    switch "$argv[1]"
+        case '-*'
+            {{cmd}} $argv
+        case '*'
+            {{cmd}} $PWD $argv
+    end
+end
+
+
+# open fastjump results in file browser
+function {{cmd}}o
+    switch "$argv[1]"
+        case '--'
+            set -e argv[1]
+        case '-*'
+            fastjump $argv
+            return
+    end
+
+    set -l output (fastjump --print0 -- $argv | string split0)
+    if test -d "$output"
+        switch $OSTYPE
+            case 'linux*'
+                xdg-open $output
+            case 'darwin*'
+                open $output
+            case cygwin
+                cygstart "" (cygpath -w -a $output)
+            case '*'
+                __fastjump_err "Unknown operating system: \"$OSTYPE\""
+        end
+    else
+        __fastjump_err "fastjump: directory '"$argv"' not found"
+        __fastjump_err "Try `fastjump --help` for more information."
+    end
+end
+
+
+# open fastjump results (child directory) in file browser
+function {{cmd}}co
+    switch "$argv[1]"
+        case '-*'
+            {{cmd}} $argv
+        case '*'
+            {{cmd}}o $PWD $argv
+    end
+end
@@ -0,0 +1,155 @@
+# fastjump integration for PowerShell, generated by `fastjump init pwsh`
+#
+# add the following line to your profile (see `$PROFILE`):
+#
+#     Invoke-Expression (& fastjump init pwsh | Out-String)
+
+$env:FASTJUMP_SOURCED = '1'
+
+# set error file location
+if ($IsMacOS) {
+    $env:FASTJUMP_ERROR_PATH = Join-Path $HOME 'Library/fastjump/errors.log'
+} elseif ($env:XDG_DATA_HOME) {
+    $env:FASTJUMP_ERROR_PATH = Join-Path $env:XDG_DATA_HOME 'fastjump/errors.log'
+} elseif ($IsWindows -or $PSVersionTable.PSEdition -eq 'Desktop') {
+    $env:FASTJUMP_ERROR_PATH = Join-Path $env:APPDATA 'fastjump/errors.log'
+} else {
+    $env:FASTJUMP_ERROR_PATH = Join-Path $HOME '.local/share/fastjump/errors.log'
+}
+
+$null = New-Item -ItemType Directory -Force -Path (Split-Path $env:FASTJUMP_ERROR_PATH)
+
+
+# record the current directory
+function __fastjump_add([string]$Dir) {
+    & fastjump --add $Dir 2>> $env:FASTJUMP_ERROR_PATH | Out-Null
+}
+
+if (-not $global:__fastjump_hooked) {
+    $global:__fastjump_hooked = $true
+{{#prompt}}
+    # on every prompt
+    $global:__fastjump_prompt = $function:prompt
+    function global:prompt {
+        if ($PWD.Provider.Name -eq 'FileSystem') {
+            __fastjump_add $PWD.ProviderPath
+        }
+        & $global:__fastjump_prompt
+    }
+{{/prompt}}
+{{#pwd}}
+    # on directory changes
+    $ExecutionContext.SessionState.InvokeCommand.LocationChangedAction = [Delegate]::Combine(
+        $ExecutionContext.SessionState.InvokeCommand.LocationChangedAction,
+        [EventHandler[System.Management.Automation.LocationChangedEventArgs]] {
+            param($Source, $Location)
+            if ($Location.NewPath.Provider.Name -eq 'FileSystem') {
+                __fastjump_add $Location.NewPath.ProviderPath
+            }
+        }
+    )
+{{/pwd}}
+}
+
+
+# enable tab completion
+$__fastjump_complete = {
+    param($CommandName, $ParameterName, $WordToComplete, $CommandAst, $FakeBoundParameters)
+    $needles = @($CommandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.Extent.Text })
+    if ($WordToComplete -eq '') {
+        $needles += ''
+    }
+    ((& fastjump --complete --print0 -- @needles) -join "`n") -split "`0" |
+        Where-Object { $_ -ne '' } |
+        ForEach-Object {
+            [System.Management.Automation.CompletionResult]::new("'$($_ -replace "'", "''")'", $_, 'ParameterValue', $_)
+        }
+}
+
+
+# misc helper functions
+function __fastjump_err([string[]]$Needles) {
+    [Console]::Error.WriteLine("fastjump: directory '$($Needles -join ' ')' not found")
+    [Console]::Error.WriteLine('Try `fastjump --help` for more information.')
+}
+
+function __fastjump_is_option([string[]]$Needles) {
+    $Needles.Count -gt 0 -and $Needles[0] -like '-*' -and $Needles[0] -ne '--'
+}
+
+function __fastjump_find([string[]]$Needles) {
+    if ($Needles.Count -gt 0 -and $Needles[0] -eq '--') {
+        $Needles = $Needles | Select-Object -Skip 1
+    }
+    $output = (((& fastjump --print0 -- @Needles) -join "`n") -split "`0")[0]
+    if ($output -and (Test-Path -LiteralPath $output -PathType Container)) {
+        $output
+    }
+}
+
+# default fastjump command
+function {{cmd}} {
+    param([string]$Needle)
+    $needles = @($Needle | Where-Object { $_ }) + $args
+    if (__fastjump_is_option $needles) {
+        & fastjump @needles
+        return
+    }
+
+    $output = __fastjump_find $needles
+    if ($output) {
+        Write-Host $output -ForegroundColor Red
+        Set-Location -LiteralPath $output
+    } else {
+        __fastjump_err $needles
+    }
+}
+
+
+# jump to child directory (subdirectory of current path)
+function {{cmd}}c {
+    param([string]$Needle)
+    $needles = @($Needle | Where-Object { $_ }) + $args
+    if (__fastjump_is_option $needles) {
+        {{cmd}} @needles
+    } else {
+        {{cmd}} $PWD.ProviderPath @needles
+    }
+}
+
+
+# open fastjump results in file browser
+function {{cmd}}o {
+    param([string]$Needle)
+    $needles = @($Needle | Where-Object { $_ }) + $args
+    if (__fastjump_is_option $needles) {
+        & fastjump @needles
+        return
+    }
+
+    $output = __fastjump_find $needles
+    if (-not $output) {
+        __fastjump_err $needles
+    } elseif ($IsLinux) {
+        & xdg-open $output
+    } elseif ($IsMacOS) {
+        & open $output
+    } else {
+        Invoke-Item -LiteralPath $output
+    }
+}
+
+
+# open fastjump results (child directory) in file browser
+function {{cmd}}co {
+    param([string]$Needle)
+    $needles = @($Needle | Where-Object { $_ }) + $args
+    if (__fastjump_is_option $needles) {
+        {{cmd}} @needles
+    } else {
+        {{cmd}}o $PWD.ProviderPath @needles
+    }
+}
+
+
+Register-ArgumentCompleter -CommandName {{cmd}}, {{cmd}}c, {{cmd}}o, {{cmd}}co -ParameterName Needle -ScriptBlock $__fastjump_complete
@@ -3,7 +3,7 @@ setlocal EnableDelayedExpansion
 
 echo %*|>nul findstr /rx \-.*
 if ERRORLEVEL 1 (
-  for /f "delims=" %%i in ('"%~dp0\fastjump" %*') do set new_path=%%i
+  for /f "delims=" %%i in ('"%~dp0\fastjump" -- %*') do set new_path=%%i
   if exist !new_path!\nul (
     echo !new_path!
     pushd !new_path!
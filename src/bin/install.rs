@@ -1,7 +1,10 @@
 use anyhow::{anyhow, bail, Result};
 use const_format::concatcp;
+use fastjump::common::config::Config as DataConfig;
 use fastjump::common::opts::InstallOpts;
 use fastjump::common::utils::{get_app_path, get_install_path, into_level};
+#[cfg(target_family = "unix")]
+use fastjump::init::{init_script, Shell};
 use fastjump::{copy_in, format_path};
 use log::{debug, info};
 use std::borrow::Cow;
@@ -17,7 +20,13 @@ use {log::warn, std::ffi::OsStr, std::fs::copy, std::fs::read_to_string, std::io
 
 const PKGNAME: &str = env!("CARGO_PKG_NAME");
 #[cfg(target_family = "unix")]
-const SUPPORTED_SHELLS: &[&str] = &["bash", "zsh", "fish", "tcsh"];
+const SUPPORTED_SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "tcsh", "nu", "elvish", "xonsh", "pwsh", "sh", "dash", "ash", "ksh",
+    "mksh",
+];
+/// The shells supported by the POSIX integration
+#[cfg(target_family = "unix")]
+const POSIX_SHELLS: &[&str] = &["sh", "dash", "ash", "ksh", "mksh"];
 
 #[derive(Default, Debug)]
 struct Config {
@@ -172,8 +181,51 @@ fn is_empty_dir(path: &Path) -> Result<bool> {
     }
 }
 
+/// Returns the name of the process which ran the installer, eg. `zsh` for
+/// `-zsh`, if it's a supported shell.
+#[cfg(target_family = "unix")]
+fn get_parent_shell() -> Option<String> {
+    let ppid = std::os::unix::process::parent_id().to_string();
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &ppid])
+        .output()
+        .ok()?;
+    let comm = String::from_utf8_lossy(&output.stdout);
+    let name = comm.trim().trim_start_matches('-');
+    let name = name.rsplit('/').next().unwrap_or(name);
+    if SUPPORTED_SHELLS.contains(&name) {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+/// Returns the shell the installer is run from.
+///
+/// The parent process is the most reliable, then `$SHELL`, which is the login
+/// shell only. The variables of the shells are the last resort, as most of
+/// them are inherited by the child shells, eg. a bash run from nushell still
+/// has `$NU_VERSION`, and `$PSModulePath` may even be set system-wide.
+/// Elvish sets none, it's found by the first two only.
 #[cfg(target_family = "unix")]
 fn get_shell() -> String {
+    if let Some(shell) = get_parent_shell() {
+        return shell;
+    }
+    let login = Path::new(
+        shellexpand::env("$SHELL")
+            .unwrap_or_else(|_| Cow::from(""))
+            .as_ref(),
+    )
+    .file_name()
+    .unwrap_or_else(|| OsStr::new(""))
+    .to_str()
+    .unwrap_or("")
+    .to_string();
+    if SUPPORTED_SHELLS.contains(&login.as_str()) {
+        return login;
+    }
+
     if let Ok(shell) = shellexpand::env("$BASH") {
         if !shell.is_empty() {
             return "bash".to_string();
@@ -194,16 +246,29 @@ fn get_shell() -> String {
             return "tcsh".to_string();
         }
     }
-    Path::new(
-        shellexpand::env("$SHELL")
-            .unwrap_or_else(|_| Cow::from(""))
-            .as_ref(),
-    )
-    .file_name()
-    .unwrap_or_else(|| OsStr::new(""))
-    .to_str()
-    .unwrap_or("")
-    .to_string()
+    if let Ok(shell) = shellexpand::env("$KSH_VERSION") {
+        if shell.contains("MIRBSD") {
+            return "mksh".to_string();
+        } else if !shell.is_empty() {
+            return "ksh".to_string();
+        }
+    }
+    if let Ok(shell) = shellexpand::env("$NU_VERSION") {
+        if !shell.is_empty() {
+            return "nu".to_string();
+        }
+    }
+    if let Ok(shell) = shellexpand::env("$XONSH_VERSION") {
+        if !shell.is_empty() {
+            return "xonsh".to_string();
+        }
+    }
+    if let Ok(shell) = shellexpand::env("$PSModulePath") {
+        if !shell.is_empty() {
+            return "pwsh".to_string();
+        }
+    }
+    login
 }
 
 fn check_opts(opts: &InstallOpts) -> Result<()> {
@@ -249,6 +314,96 @@ fn create_dir_dryrun(dir: &Path, dryrun: bool) -> Result<()> {
     Ok(())
 }
 
+/// Returns the code of the shell which puts the bin directory of the
+/// installation in front of the `PATH`.
+#[cfg(target_family = "unix")]
+fn prepend_path(shell: Shell, bin_dir: &Path) -> Result<String> {
+    // the directory is quoted as is, without escaping
+    let dir = bin_dir
+        .to_str()
+        .filter(|x| !x.contains(['\'', '\\']))
+        .ok_or_else(|| anyhow!("unsupported bin directory {}", bin_dir.display()))?;
+    Ok(match shell {
+        Shell::Nu => format!("$env.PATH = ($env.PATH | prepend '{}')", dir),
+        Shell::Elvish => format!("set paths = ['{}' $@paths]", dir),
+        Shell::Xonsh => format!("$PATH.insert(0, '{}')", dir),
+        Shell::Pwsh => format!(
+            "$env:PATH = '{}' + [IO.Path]::PathSeparator + $env:PATH",
+            dir
+        ),
+        Shell::Posix => format!("PATH='{}':\"${{PATH}}\"\nexport PATH", dir),
+        _ => bail!("{:?} has an installed script of its own", shell),
+    })
+}
+
+/// Writes the integration code of `fastjump init` for the shell, the one
+/// with the default hook, to the file.
+#[cfg(target_family = "unix")]
+fn write_init_dryrun(shell: Shell, file: &Path, bin_dir: &Path, dryrun: bool) -> Result<()> {
+    info!("Writing the {:?} integration => {}", shell, file.display());
+    let script = format!(
+        "# set user installation paths\n{}\n\n{}",
+        prepend_path(shell, bin_dir)?,
+        init_script(shell, "j", None)?
+    );
+    if !dryrun {
+        std::fs::write(file, script)?;
+    }
+    Ok(())
+}
+
+/// Returns the default location of nushell's config.nu, i.e. `$nu.config-path`.
+#[cfg(target_family = "unix")]
+fn get_nu_config_file() -> String {
+    match shellexpand::env("$XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => format!("{}/nushell/config.nu", dir),
+        _ if cfg!(target_os = "macos") => {
+            "~/Library/Application Support/nushell/config.nu".to_string()
+        }
+        _ => "~/.config/nushell/config.nu".to_string(),
+    }
+}
+
+/// Returns the location of elvish's rc.elv, the legacy one if it's in use.
+#[cfg(target_family = "unix")]
+fn get_elvish_rc_file() -> String {
+    if Path::new(shellexpand::tilde("~/.elvish/rc.elv").as_ref()).exists() {
+        return "~/.elvish/rc.elv".to_string();
+    }
+    match shellexpand::env("$XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => format!("{}/elvish/rc.elv", dir),
+        _ => "~/.config/elvish/rc.elv".to_string(),
+    }
+}
+
+/// Returns the location of PowerShell's profile, i.e. `$PROFILE`.
+#[cfg(target_family = "unix")]
+fn get_pwsh_profile() -> String {
+    match shellexpand::env("$XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => {
+            format!("{}/powershell/Microsoft.PowerShell_profile.ps1", dir)
+        }
+        _ => "~/.config/powershell/Microsoft.PowerShell_profile.ps1".to_string(),
+    }
+}
+
+/// Returns the rc file of the POSIX shells, which is named by `$ENV` for sh.
+///
+/// `$ENV` is subject to parameter expansion by the shell, eg. `$HOME/.shrc`.
+#[cfg(target_family = "unix")]
+fn get_posix_rc_file(shell: &str) -> String {
+    match shell {
+        "ksh" => "~/.kshrc".to_string(),
+        "mksh" => "~/.mkshrc".to_string(),
+        _ => match shellexpand::env("$ENV") {
+            Ok(env) if !env.is_empty() => shellexpand::full(&env)
+                .map(|x| x.to_string())
+                .unwrap_or_else(|_| env.to_string()),
+            _ => "~/.shrc".to_string(),
+        },
+    }
+}
+
 #[cfg(target_family = "unix")]
 fn get_rc_file(etc_dir: &Path, share_dir: &Path) -> (String, String) {
     let rcfile;
@@ -257,6 +412,30 @@ fn get_rc_file(etc_dir: &Path, share_dir: &Path) -> (String, String) {
         let aj_shell = format!("{}/{}.fish", share_dir.display(), PKGNAME);
         source_msg = format!("if test -f {}; . {}; end", aj_shell, aj_shell);
         rcfile = "~/.config/fish/config.fish".to_string();
+    } else if get_shell() == "nu" {
+        let aj_shell = format!("{}/{}.nu", share_dir.display(), PKGNAME);
+        // nushell resolves the sourced file at parse time, it must exist
+        source_msg = format!("source {}", aj_shell);
+        rcfile = get_nu_config_file();
+    } else if get_shell() == "elvish" {
+        let aj_shell = format!("{}/{}.elv", share_dir.display(), PKGNAME);
+        // the commands are added to the REPL by the script itself
+        source_msg = format!("eval (slurp < {})", aj_shell);
+        rcfile = get_elvish_rc_file();
+    } else if get_shell() == "xonsh" {
+        let aj_shell = format!("{}/{}.xsh", share_dir.display(), PKGNAME);
+        source_msg = format!("source {}", aj_shell);
+        rcfile = "~/.xonshrc".to_string();
+    } else if get_shell() == "pwsh" {
+        let aj_shell = format!("{}/{}.ps1", share_dir.display(), PKGNAME);
+        // dot sourced, the functions are defined in the global scope
+        source_msg = format!(". {}", aj_shell);
+        rcfile = get_pwsh_profile();
+    } else if POSIX_SHELLS.contains(&get_shell().as_str()) {
+        let aj_shell = format!("{}/{}.sh", etc_dir.display(), PKGNAME);
+        // no bash-isms, `[[` and `source` are not available
+        source_msg = format!("[ -s {} ] && . {}", aj_shell, aj_shell);
+        rcfile = get_posix_rc_file(&get_shell());
     } else {
         let aj_shell = format!("{}/{}.sh", etc_dir.display(), PKGNAME);
         source_msg = format!("[[ -s {} ]] && source {}", aj_shell, aj_shell);
@@ -283,6 +462,15 @@ fn post_install(_etc_dir: &Path, _share_dir: &Path, _bin_dir: &Path, _dryrun: bo
         if get_shell() == "zsh" {
             println!("\n\tautoload -U compinit && compinit -u");
         }
+        if matches!(get_shell().as_str(), "sh" | "dash" | "ash")
+            && shellexpand::env("$ENV").map_or(true, |x| x.is_empty())
+        {
+            info!(
+                "Please add the following line to ~/.profile to load {}:",
+                rcfile
+            );
+            info!("export ENV={}", rcfile);
+        }
 
         info!("Add {} to the rcfile {}", PKGNAME, rcfile);
         if let Err(e) = modify_bin_rcfile(&rcfile, &source_msg, _dryrun, true) {
@@ -298,13 +486,25 @@ fn post_install(_etc_dir: &Path, _share_dir: &Path, _bin_dir: &Path, _dryrun: bo
             "If you want to try '{}' in the current shell, please run the following line manually.",
             PKGNAME
         );
-        info!(
-            "source {}",
-            source_msg
-                .split_whitespace()
-                .last()
-                .unwrap_or("Error: no source file found")
-        );
+        if matches!(get_shell().as_str(), "elvish" | "pwsh") {
+            info!("{}", source_msg);
+        } else if POSIX_SHELLS.contains(&get_shell().as_str()) {
+            info!(
+                ". {}",
+                source_msg
+                    .split_whitespace()
+                    .last()
+                    .unwrap_or("Error: no source file found")
+            );
+        } else {
+            info!(
+                "source {}",
+                source_msg
+                    .split_whitespace()
+                    .last()
+                    .unwrap_or("Error: no source file found")
+            );
+        }
     }
 
     Ok(())
@@ -318,13 +518,17 @@ fn modify_bin_rcfile(rcfile: &str, source_msg: &str, dryrun: bool, install: bool
     }
     let rcfile = shellexpand::tilde(rcfile);
     if install {
+        // the profiles of some shells, eg. PowerShell, don't exist by default
+        if let Some(parent) = Path::new(rcfile.as_ref()).parent() {
+            create_dir_all(parent)?;
+        }
         let mut file = OpenOptions::new()
-            .write(true)
+            .create(true)
             .append(true)
             .open(rcfile.as_ref())?;
         writeln!(file, "\n{}\n", source_msg)?;
     } else {
-        copy(rcfile.as_ref(), (rcfile.to_owned() + ".bak").as_ref())?;
+        copy(rcfile.as_ref(), format!("{}.bak", rcfile))?;
 
         let lines = read_to_string(rcfile.as_ref())?;
         let lines = lines.lines().filter(|line| !line.contains(source_msg));
@@ -336,7 +540,7 @@ fn modify_bin_rcfile(rcfile: &str, source_msg: &str, dryrun: bool, install: bool
         let mut buffer = LineWriter::new(file);
         for line in lines {
             buffer.write_all(line.as_bytes())?;
-            buffer.write_all(&[b'\n'])?;
+            buffer.write_all(b"\n")?;
         }
         buffer.flush()?;
     }
@@ -377,7 +581,7 @@ fn modify_bin_sh(etc_dir: &Path, share_dir: &Path, dryrun: bool) -> Result<()> {
         "\
         \n# check custom install \
         \nif [ -s {}/{}.${{shell}} ]; then \
-        \n    source {}/{}.${{shell}} \
+        \n    . {}/{}.${{shell}} \
         \nfi\n",
         share_dir.display(),
         PKGNAME,
@@ -386,7 +590,7 @@ fn modify_bin_sh(etc_dir: &Path, share_dir: &Path, dryrun: bool) -> Result<()> {
     );
 
     let etc_file = etc_dir.join(concatcp!(PKGNAME, ".sh"));
-    let mut file = OpenOptions::new().write(true).open(etc_file)?;
+    let mut file = OpenOptions::new().append(true).open(etc_file)?;
     file.write_all(custom_install.as_bytes())?;
 
     Ok(())
@@ -519,6 +723,21 @@ fn handle_install(config: &Config, opts: &InstallOpts) -> Result<()> {
             &config.zshshare_dir,
             opts.dryrun,
         )?;
+        // the shells without a script of their own get the one of `init`
+        for (shell, ext) in [
+            (Shell::Nu, "nu"),
+            (Shell::Elvish, "elv"),
+            (Shell::Xonsh, "xsh"),
+            (Shell::Pwsh, "ps1"),
+            (Shell::Posix, "posix"),
+        ] {
+            write_init_dryrun(
+                shell,
+                &config.share_dir.join(format!("{}.{}", PKGNAME, ext)),
+                &config.bin_dir,
+                opts.dryrun,
+            )?;
+        }
 
         if config.custom_install {
             modify_bin_sh(&config.etc_dir, &config.share_dir, opts.dryrun)?;
@@ -622,6 +841,11 @@ fn remove_system_installation(config: &mut Config, dryrun: bool) -> Result<()> {
         rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".bash")), dryrun)?;
         rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".fish")), dryrun)?;
         rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".tcsh")), dryrun)?;
+        rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".nu")), dryrun)?;
+        rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".elv")), dryrun)?;
+        rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".xsh")), dryrun)?;
+        rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".ps1")), dryrun)?;
+        rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".posix")), dryrun)?;
         rm_dryrun(&config.share_dir.join(concatcp!(PKGNAME, ".zsh")), dryrun)?;
         rmdir_dryrun(&config.share_dir, dryrun)?;
         rm_dryrun(&config.zshshare_dir.join("_j"), dryrun)?;
@@ -664,11 +888,40 @@ fn cleanup_source_file(config: &Config, dryrun: bool) -> Result<()> {
     Ok(())
 }
 
-fn remove_user_data(dryrun: bool) -> Result<()> {
-    let data_home = get_app_path().join(PKGNAME);
-    if data_home.exists() {
-        info!("Found user data...");
-        rmdir_dryrun(&data_home, dryrun)?;
+/// Removes the databases, the one of the profile only if it's selected.
+///
+/// A data directory set by the user may hold other files, so only the files
+/// of the databases are removed from it.
+fn remove_user_data(opts: &InstallOpts) -> Result<()> {
+    let profile = opts.profile.is_some() || std::env::var_os("FASTJUMP_PROFILE").is_some();
+    let data = DataConfig::new(opts.profile.clone(), opts.data_dir.clone());
+    if !data.data_dir.exists() {
+        return Ok(());
+    }
+    info!("Found user data...");
+
+    if profile {
+        for file in [
+            &data.data_path,
+            &data.backup_path,
+            &data.completion_path,
+            &data.socket_path,
+        ] {
+            rm_dryrun(file, opts.dryrun)?;
+        }
+    } else if data.data_dir == get_app_path().join(PKGNAME) {
+        rmdir_dryrun(&data.data_dir, opts.dryrun)?;
+    } else {
+        for entry in data.data_dir.read_dir()? {
+            let file = entry?.path();
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if [".db", ".db.bak", ".completion", ".sock"]
+                .iter()
+                .any(|x| name.ends_with(x))
+            {
+                rm_dryrun(&file, opts.dryrun)?;
+            }
+        }
     }
     Ok(())
 }
@@ -686,7 +939,7 @@ fn handle_uninstall(config: &mut Config, opts: &InstallOpts) -> Result<()> {
     remove_custom_installation(config, opts.dryrun)?;
     remove_system_installation(config, opts.dryrun)?;
     if opts.purge {
-        remove_user_data(opts.dryrun)?;
+        remove_user_data(opts)?;
     }
 
     Ok(())
@@ -1,23 +1,160 @@
-use crate::common::utils::get_app_path;
-use const_format::concatcp;
+use crate::common::output::OutputFormat;
+use crate::common::utils::{absolute_path, expand_home, get_app_path};
+use crate::common::weighting::Weighting;
+use anyhow::{anyhow, Result};
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 const PKGNAME: &str = env!("CARGO_PKG_NAME");
 
+/// The name of a separate database, eg. `work`.
+///
+/// The profile `default` is the database used without any profile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile(String);
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+        if s.is_empty() || s.starts_with('.') || !s.chars().all(valid) {
+            return Err(format!(
+                "invalid profile {}, only letters, digits, -, _ and . are allowed",
+                s
+            ));
+        }
+        Ok(Profile(s.to_string()))
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Profile {
+    /// Returns the name of the files of the profile, without the extensions.
+    fn file_stem(&self) -> &str {
+        if self.0 == "default" {
+            PKGNAME
+        } else {
+            &self.0
+        }
+    }
+}
+
 pub struct Config {
+    /// The directory of the databases of all of the profiles
+    pub data_dir: PathBuf,
     pub data_path: PathBuf,
     pub backup_path: PathBuf,
+    pub completion_path: PathBuf,
+    /// The socket of the daemon holding the database in memory, if it runs
+    pub socket_path: PathBuf,
+    /// How many entries are offered by the tab completion
+    pub completion_count: usize,
+    /// How the weights change when the entries are visited
+    pub weighting: Weighting,
+    /// How many days the entries found missing by jumps are kept before being
+    /// forgotten, never if 0
+    pub forget_days: u64,
+    /// Record the directories by their paths with the symlinks resolved
+    pub canonical: bool,
+    /// The format of the results on stdout, only ever set by `--output` so
+    /// that the shell integration always gets the text
+    pub output: OutputFormat,
+}
+
+/// Reads the setting from the environment variable `FASTJUMP_<name>`, or
+/// falls back to the default if it's not set or malformed.
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
+
+/// Reads the setting from the environment variable `FASTJUMP_<name>`, if it's
+/// set and well-formed.
+pub fn env_opt<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(format!("{}_{}", PKGNAME.to_ascii_uppercase(), name))
+        .ok()
+        .filter(|x| !x.is_empty())
+        .and_then(|x| x.parse().ok())
+}
+
+/// Reads the setting from `FASTJUMP_<name>` like `env_opt`, but fails if it's
+/// set and malformed rather than ignoring it.
+pub fn env_strict<T: FromStr<Err = String>>(name: &str) -> Result<Option<T>> {
+    let name = format!("{}_{}", PKGNAME.to_ascii_uppercase(), name);
+    match std::env::var(&name) {
+        Ok(x) if !x.is_empty() => x.parse().map(Some).map_err(|e| anyhow!("{}: {}", name, e)),
+        _ => Ok(None),
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
-        let data_path: PathBuf = [PKGNAME, concatcp!(PKGNAME, ".db")].iter().collect();
-        let backup_path: PathBuf = [PKGNAME, concatcp!(PKGNAME, ".db.bak")].iter().collect();
-        let data_home = get_app_path();
+        Config::new(None, None)
+    }
+}
+
+impl Config {
+    /// Returns the config of the database of the profile in the directory,
+    /// or the ones set by `FASTJUMP_PROFILE` and `FASTJUMP_DATA_DIR` if they
+    /// are not given.
+    pub fn new(profile: Option<Profile>, data_dir: Option<PathBuf>) -> Self {
+        let profile = profile.or_else(|| env_opt("PROFILE"));
+        let stem = profile.as_ref().map_or(PKGNAME, |x| x.file_stem());
+        let data_dir = match data_dir.or_else(|| env_opt("DATA_DIR")) {
+            Some(dir) => absolute_path(expand_home(&dir)),
+            None => get_app_path().join(PKGNAME),
+        };
 
         Config {
-            data_path: data_home.join(data_path),
-            backup_path: data_home.join(backup_path),
+            data_path: data_dir.join(format!("{}.db", stem)),
+            backup_path: data_dir.join(format!("{}.db.bak", stem)),
+            completion_path: data_dir.join(format!("{}.completion", stem)),
+            socket_path: data_dir.join(format!("{}.sock", stem)),
+            data_dir,
+            completion_count: env_or("COMPLETION_COUNT", 9),
+            weighting: env_or("WEIGHTING", Weighting::default()),
+            forget_days: env_or("FORGET_DAYS", 7),
+            canonical: env_or("CANONICAL", false),
+            output: OutputFormat::default(),
         }
     }
+
+    /// Fails on the malformed settings which must not fall back to their
+    /// defaults, eg. the weights of a misspelled weighting would be computed
+    /// by another one.
+    pub fn check_env() -> Result<()> {
+        env_strict::<Weighting>("WEIGHTING")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_profile() {
+        assert!("work".parse::<Profile>().is_ok());
+        assert!("my-work_2.0".parse::<Profile>().is_ok());
+        assert!("".parse::<Profile>().is_err());
+        assert!("..".parse::<Profile>().is_err());
+        assert!("a/b".parse::<Profile>().is_err());
+
+        let config = Config::new(Some("work".parse().unwrap()), Some(PathBuf::from("/data")));
+        assert_eq!(config.data_path, Path::new("/data/work.db"));
+        assert_eq!(config.backup_path, Path::new("/data/work.db.bak"));
+        assert_eq!(config.socket_path, Path::new("/data/work.sock"));
+        let config = Config::new(
+            Some("default".parse().unwrap()),
+            Some(PathBuf::from("/data")),
+        );
+        assert_eq!(config.data_path, Path::new("/data/fastjump.db"));
+    }
 }
@@ -0,0 +1,119 @@
+use log::debug;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the check of a path is waited for, eg. on a hung NFS mount.
+pub const CHECK_TIMEOUT: Duration = Duration::from_millis(200);
+/// How many paths are checked at the same time, ahead of the one needed.
+const CHECK_WINDOW: usize = 8;
+
+/// Returns the first `count` paths which exist in their order, and the ones
+/// found missing before them, which may also be the ones which can't be
+/// accessed, eg. for lack of permission.
+///
+/// See `first_existing_by`.
+pub fn first_existing<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    count: usize,
+) -> (Vec<&'a Path>, Vec<&'a Path>) {
+    first_existing_by(paths, count, CHECK_TIMEOUT, |x| x.exists())
+}
+
+/// Returns the first `count` paths which pass the check in their order, and
+/// the ones which fail it before them.
+///
+/// The paths are checked in parallel a few ahead of the one needed, so that
+/// the checks stop as soon as enough of them are found. A path whose check
+/// doesn't finish in time is skipped, and its check is left behind rather
+/// than blocking the caller.
+pub fn first_existing_by<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    count: usize,
+    timeout: Duration,
+    exists: fn(&Path) -> bool,
+) -> (Vec<&'a Path>, Vec<&'a Path>) {
+    let mut seen = HashSet::new();
+    let paths: Vec<_> = paths.into_iter().filter(|x| seen.insert(*x)).collect();
+    let (sender, receiver) = channel();
+    let mut started = Vec::with_capacity(paths.len());
+    let mut results = vec![None; paths.len()];
+    let mut ret = vec![];
+    let mut missing = vec![];
+
+    for i in 0..paths.len() {
+        if ret.len() >= count {
+            break;
+        }
+        while started.len() < paths.len() && started.len() < i + CHECK_WINDOW {
+            let (j, path) = (started.len(), paths[started.len()].to_path_buf());
+            let sender = sender.clone();
+            thread::spawn(move || sender.send((j, exists(&path))));
+            started.push(Instant::now());
+        }
+
+        let deadline = started[i] + timeout;
+        while results[i].is_none() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(timeout) {
+                Ok((j, result)) => results[j] = Some(result),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        match results[i] {
+            Some(true) => ret.push(paths[i]),
+            Some(false) => {
+                debug!("skipped {} which doesn't exist", paths[i].display());
+                missing.push(paths[i]);
+            }
+            None => debug!(
+                "skipped {} which can't be checked in time",
+                paths[i].display()
+            ),
+        }
+    }
+    (ret, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(path: &Path) -> bool {
+        if path.starts_with("/hung") {
+            thread::sleep(Duration::from_secs(10));
+        }
+        !path.starts_with("/missing")
+    }
+
+    #[test]
+    fn test_first_existing() {
+        let paths: Vec<_> = ["/missing", "/hung", "/a", "/a", "/b", "/c"]
+            .iter()
+            .map(Path::new)
+            .collect();
+        let start = Instant::now();
+        assert_eq!(
+            first_existing_by(paths.clone(), 2, Duration::from_millis(50), check),
+            (
+                vec![Path::new("/a"), Path::new("/b")],
+                vec![Path::new("/missing")]
+            )
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        assert_eq!(
+            first_existing_by(paths, 10, Duration::from_millis(50), check)
+                .0
+                .len(),
+            3
+        );
+        assert!(
+            first_existing_by(vec![], 1, Duration::from_millis(50), check)
+                .0
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::common::utils::contains_bytes;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+/// A trigram packed into the lower 24 bits of a u32.
+type Trigram = u32;
+
+/// An entry of the database with its lookup keys precomputed.
+///
+/// The keys are kept as the raw bytes of the path, so that paths which are not
+/// valid UTF-8 are matched as they are. The weight is not, so that changing the
+/// weights doesn't invalidate the index.
+#[derive(Debug)]
+pub struct IndexEntry {
+    pub path: PathBuf,
+    /// The path bytes, ASCII lowercased
+    lower: Vec<u8>,
+    /// Byte offset of the last component of the path
+    name_start: usize,
+    /// Byte offset of the end of the last component, trailing separators excluded
+    name_end: usize,
+    /// Number of chars of the last component
+    name_chars: usize,
+}
+
+impl IndexEntry {
+    fn new(path: &Path) -> Self {
+        let original = path.as_os_str().as_encoded_bytes();
+        let name_end = original
+            .iter()
+            .rposition(|x| *x != MAIN_SEPARATOR as u8)
+            .map_or(0, |i| i + 1);
+        let name_start = original[..name_end]
+            .iter()
+            .rposition(|x| *x == MAIN_SEPARATOR as u8)
+            .map_or(0, |i| i + 1);
+
+        IndexEntry {
+            path: path.to_path_buf(),
+            lower: original.to_ascii_lowercase(),
+            name_start,
+            name_end,
+            name_chars: String::from_utf8_lossy(&original[name_start..name_end])
+                .chars()
+                .count(),
+        }
+    }
+
+    /// The path bytes, case preserved.
+    pub fn original(&self) -> &[u8] {
+        self.path.as_os_str().as_encoded_bytes()
+    }
+
+    /// The path bytes, ASCII lowercased.
+    pub fn lower(&self) -> &[u8] {
+        &self.lower
+    }
+
+    /// The last component of the path, case preserved.
+    pub fn name(&self) -> &[u8] {
+        &self.original()[self.name_start..self.name_end]
+    }
+
+    /// The last component of the path, ASCII lowercased.
+    pub fn lower_name(&self) -> &[u8] {
+        &self.lower[self.name_start..self.name_end]
+    }
+
+    /// The number of chars of the last component of the path.
+    pub fn name_chars(&self) -> usize {
+        self.name_chars
+    }
+}
+
+/// A lowercased component and trigram index over the database entries.
+///
+/// Matchers consult the index to prune the candidates before doing the real
+/// (and more expensive) matching, so that a query doesn't have to convert and
+/// lowercase every key of the database again and again.
+///
+/// The entries are converted once when the index is built, the trigram table
+/// is only built for the databases held in memory, see `Database::keep_index`,
+/// as it takes much longer than a single scan of the entries, which pays off
+/// only if the index is queried repeatedly.
+#[derive(Debug, Default)]
+pub struct MatchIndex {
+    entries: Vec<IndexEntry>,
+    trigrams: OnceCell<HashMap<Trigram, Vec<u32>>>,
+}
+
+fn trigrams(s: &[u8]) -> impl Iterator<Item = Trigram> + '_ {
+    s.windows(3)
+        .map(|w| (w[0] as u32) << 16 | (w[1] as u32) << 8 | w[2] as u32)
+}
+
+/// Intersect two sorted posting lists.
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = Vec::with_capacity(a.len().min(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                ret.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    ret
+}
+
+impl MatchIndex {
+    pub fn new<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> Self {
+        MatchIndex {
+            entries: paths.map(|x| IndexEntry::new(x)).collect(),
+            trigrams: OnceCell::new(),
+        }
+    }
+
+    /// Builds the trigram table used to prune the candidates, for the
+    /// long-lived indexes which are queried more than once.
+    pub fn build_trigrams(&self) {
+        self.trigrams.get_or_init(|| {
+            let mut table: HashMap<Trigram, Vec<u32>> = HashMap::new();
+            for (id, entry) in self.entries.iter().enumerate() {
+                let id = id as u32;
+                for trigram in trigrams(&entry.lower) {
+                    let postings = table.entry(trigram).or_default();
+                    // a path can contain the same trigram more than once
+                    if postings.last() != Some(&id) {
+                        postings.push(id);
+                    }
+                }
+            }
+            table
+        });
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the entries which may contain all of the needles, i.e. each
+    /// lowercased needle is a part of the lowercased path, or shares all of
+    /// its trigrams with it if the trigram table has been built.
+    ///
+    /// Needles shorter than a trigram don't narrow down the candidates in the
+    /// latter case.
+    pub fn candidates<'a>(&'a self, needles: &[&[u8]]) -> Vec<&'a IndexEntry> {
+        let needles: Vec<_> = needles.iter().map(|x| x.to_ascii_lowercase()).collect();
+        let table = match self.trigrams.get() {
+            Some(table) => table,
+            None => {
+                return self
+                    .entries
+                    .iter()
+                    .filter(|x| needles.iter().all(|n| contains_bytes(&x.lower, n)))
+                    .collect()
+            }
+        };
+
+        let mut lists: Vec<&[u32]> = Vec::new();
+        for needle in &needles {
+            for trigram in trigrams(needle) {
+                match table.get(&trigram) {
+                    Some(postings) => lists.push(postings),
+                    None => return Vec::new(),
+                }
+            }
+        }
+        if lists.is_empty() {
+            return self.entries.iter().collect();
+        }
+
+        lists.sort_unstable_by_key(|x| x.len());
+        let mut ids = lists[0].to_vec();
+        for list in &lists[1..] {
+            if ids.is_empty() {
+                break;
+            }
+            ids = intersect(&ids, list);
+        }
+        ids.into_iter()
+            .map(|id| &self.entries[id as usize])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::iter::FromIterator;
+
+    #[test]
+    fn test_candidates() {
+        let test_set = [
+            vec!["", "Foo", "bar"],
+            vec!["", "foo", "baz"],
+            vec!["", "qux"],
+        ];
+        let data: HashMap<PathBuf, f32> =
+            HashMap::from_iter(test_set.iter().map(|x| (x.iter().collect(), 10.0f32)));
+        let index = MatchIndex::new(data.keys());
+        index.build_trigrams();
+
+        let found = |needles: &[&str]| {
+            let needles: Vec<_> = needles.iter().map(|x| x.as_bytes()).collect();
+            let mut ret: Vec<_> = index
+                .candidates(&needles)
+                .iter()
+                .map(|x| x.path.clone())
+                .collect();
+            ret.sort();
+            ret
+        };
+        let path = |x: &[&str]| x.iter().collect::<PathBuf>();
+
+        assert_eq!(
+            found(&["foo"]),
+            vec![path(&test_set[0]), path(&test_set[1])]
+        );
+        assert_eq!(found(&["FOO", "bar"]), vec![path(&test_set[0])]);
+        assert_eq!(found(&["nothing"]), Vec::<PathBuf>::new());
+        // too short to prune anything
+        assert_eq!(found(&["q"]).len(), test_set.len());
+    }
+
+    #[test]
+    fn test_entry_name() {
+        let entry = IndexEntry::new(&["", "Foo", "Bar"].iter().collect::<PathBuf>());
+        assert_eq!(entry.name(), b"Bar");
+        assert_eq!(entry.lower_name(), b"bar");
+        assert_eq!(entry.name_chars(), 3);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_non_utf8_entry() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/foo/B\xffr/"));
+        let entry = IndexEntry::new(path);
+        assert_eq!(entry.original(), b"/foo/B\xffr/");
+        assert_eq!(entry.name(), b"B\xffr");
+        assert_eq!(entry.lower_name(), b"b\xffr");
+        assert_eq!(entry.name_chars(), 3);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::common::utils::contains_bytes;
 use crate::database::Database;
 use lazy_static::lazy_static;
 use log::{debug, trace};
@@ -34,6 +35,14 @@ impl MakeAsciiLowercaseCow for Cow<'_, str> {
     }
 }
 
+impl MakeAsciiLowercaseCow for Cow<'_, [u8]> {
+    fn make_ascii_lowercase_cow(&mut self) {
+        if self.iter().any(|x| x.is_ascii_uppercase()) {
+            self.to_mut().make_ascii_lowercase();
+        }
+    }
+}
+
 impl MakeAsciiUppercaseCow for Cow<'_, str> {
     fn make_ascii_uppercase_cow(&mut self) {
         if self.chars().any(|x| x.is_ascii_lowercase()) {
@@ -42,6 +51,20 @@ impl MakeAsciiUppercaseCow for Cow<'_, str> {
     }
 }
 
+/// Converts the needles into raw bytes, ASCII lowercased if ignoring case.
+fn needles_to_bytes<'a>(needles: &'a [&Path], ignore_case: bool) -> Vec<Cow<'a, [u8]>> {
+    needles
+        .iter()
+        .map(|x| {
+            let mut needle = Cow::from(x.as_os_str().as_encoded_bytes());
+            if ignore_case {
+                needle.make_ascii_lowercase_cow();
+            }
+            needle
+        })
+        .collect()
+}
+
 /// Matches needles anywhere in the path as long as they're in the same (but
 /// not necessarily consecutive) order.
 ///
@@ -52,28 +75,21 @@ pub fn match_anywhere<'a>(
     ignore_case: bool,
 ) -> Vec<(&'a Path, f32)> {
     let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
+    let needles = needles_to_bytes(needles, ignore_case);
+    let keys: Vec<&[u8]> = needles.iter().map(|x| x.as_ref()).collect();
 
-    for (k, v) in data.iter() {
-        let mut path = k.to_string_lossy();
-        path.make_ascii_lowercase_cow();
-
-        let contains = &mut |_, needle: &&Path| {
-            // TODO: do overlapped cases matter?
-            // TODO: does needles order matter?
-            if ignore_case {
-                let mut needle = needle.to_string_lossy();
-                needle.make_ascii_lowercase_cow();
-                if !path.contains(needle.as_ref()) {
-                    return None;
-                }
-            } else if !path.contains(needle.to_string_lossy().as_ref()) {
-                return None;
-            }
-            Some(())
+    for entry in data.index().candidates(&keys) {
+        let path = if ignore_case {
+            entry.lower()
+        } else {
+            entry.original()
         };
-        if needles.iter().try_fold((), contains) != None {
-            trace!("pushing ({}, {})", k.to_string_lossy(), v);
-            candidates.push((k, *v));
+        // TODO: do overlapped cases matter?
+        // TODO: does needles order matter?
+        if keys.iter().all(|needle| contains_bytes(path, needle)) {
+            let weight = data[&entry.path];
+            trace!("pushing ({}, {})", entry.path.display(), weight);
+            candidates.push((&entry.path, weight));
         }
     }
     candidates
@@ -90,34 +106,25 @@ pub fn match_consecutive<'a>(
     ignore_case: bool,
 ) -> Vec<(&'a Path, f32)> {
     let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
+    let needles = needles_to_bytes(needles, ignore_case);
+    let keys: Vec<&[u8]> = needles.iter().map(|x| x.as_ref()).collect();
 
-    for (k, v) in data.iter() {
+    for entry in data.index().candidates(&keys) {
         // we don't use components as the path has been normalized
-        let path = k.to_string_lossy();
-        let mut part_iter = path.split(MAIN_SEPARATOR).rev();
-        if needles.iter().rev().try_fold((), |_, needle| {
-            if let Some(part) = part_iter.next() {
-                if ignore_case {
-                    let mut part = Cow::from(part);
-                    part.make_ascii_lowercase_cow();
-                    let mut needle = needle.to_string_lossy();
-                    needle.make_ascii_lowercase_cow();
-
-                    if !part.contains(needle.as_ref()) {
-                        return None;
-                    }
-                } else if !part.contains(needle.to_string_lossy().as_ref()) {
-                    return None;
-                }
-            } else {
-                return None;
-            }
-            Some(())
-        }) != None
+        let path = if ignore_case {
+            entry.lower()
+        } else {
+            entry.original()
+        };
+        let mut part_iter = path.split(|x| *x == MAIN_SEPARATOR as u8).rev();
+        if keys
+            .iter()
+            .rev()
+            .all(|needle| matches!(part_iter.next(), Some(part) if contains_bytes(part, needle)))
         {
-            let path = k.to_string_lossy();
-            trace!("pushing ({}, {})", path, v);
-            candidates.push((k, *v));
+            let weight = data[&entry.path];
+            trace!("pushing ({}, {})", entry.path.display(), weight);
+            candidates.push((&entry.path, weight));
         }
     }
     candidates
@@ -135,41 +142,84 @@ pub fn match_fuzzy<'a>(
     ignore_case: bool,
     threshold: Option<f64>,
 ) -> Vec<(&'a Path, f32)> {
-    let end_dir = |path: &'a Path| -> Cow<'a, str> {
-        path.file_name()
-            .expect("expect a non-empty path")
-            .to_string_lossy()
-    };
+    let threshold = threshold.unwrap_or(*FUZZY_MATCH_THRESHOLD);
     let mut needle = needles
         .last()
         .expect("Expect a non-empty path to search")
         .to_string_lossy();
     needle.make_ascii_lowercase_cow();
-    let mut match_percent: Box<dyn FnMut(&'a Path) -> f64> = if ignore_case {
-        Box::new(|path: &Path| {
-            let mut end = end_dir(path);
-            end.make_ascii_lowercase_cow();
-            normalized_levenshtein(needle.as_ref(), end.as_ref())
-        })
-    } else {
-        Box::new(|path: &Path| normalized_levenshtein(needle.as_ref(), end_dir(path).as_ref()))
-    };
-    let mut meets_threshold = |path: &'a Path| {
-        let score = match_percent(path);
-        debug!("fuzzy score {}: {}", path.to_string_lossy(), score);
-        score >= threshold.unwrap_or(*FUZZY_MATCH_THRESHOLD)
-    };
+    let needle_chars = needle.chars().count();
     let mut candidates: Vec<(&'a Path, f32)> = Vec::with_capacity(ENTRIES_COUNT);
 
-    for (k, v) in data.iter() {
-        if meets_threshold(k) {
-            trace!("pushing ({}, {})", k.to_string_lossy(), v);
-            candidates.push((k, *v));
+    for entry in data.index().entries() {
+        // the edit distance is at least the difference of the lengths, skip
+        // the entries that can't reach the threshold anyway
+        let max_chars = needle_chars.max(entry.name_chars());
+        let diff_chars = (needle_chars as isize - entry.name_chars() as isize).abs();
+        if max_chars > 0 && 1.0 - (diff_chars as f64) / (max_chars as f64) < threshold {
+            continue;
+        }
+
+        let end = String::from_utf8_lossy(if ignore_case {
+            entry.lower_name()
+        } else {
+            entry.name()
+        });
+        let score = normalized_levenshtein(needle.as_ref(), end.as_ref());
+        debug!("fuzzy score {}: {}", entry.path.display(), score);
+        if score >= threshold {
+            let weight = data[&entry.path];
+            trace!("pushing ({}, {})", entry.path.display(), weight);
+            candidates.push((&entry.path, weight));
         }
     }
     candidates
 }
 
+/// Checks if the bytes match a glob pattern, where `*` matches any bytes,
+/// path separators included, and `?` matches a single byte.
+pub fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was and the text it started to match at
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(x) if *x == b'?' || *x == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // let the `*` match one more byte
+                Some((sp, st)) => {
+                    p = sp + 1;
+                    t = st + 1;
+                    star = Some((sp, st + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|x| *x == b'*')
+}
+
+/// Matches the paths against glob patterns, see `glob_match`.
+pub fn match_glob<'a>(patterns: &[&Path], data: &'a Database) -> Vec<(&'a Path, f32)> {
+    data.iter()
+        .filter(|(path, _)| {
+            let path = path.as_os_str().as_encoded_bytes();
+            patterns
+                .iter()
+                .any(|x| glob_match(x.as_os_str().as_encoded_bytes(), path))
+        })
+        .map(|(path, weight)| (path.as_path(), *weight))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,7 +248,7 @@ mod tests {
             test_set.iter().map(|x| (x.0.iter().collect(), 10.0f32)),
         ));
         let results = match_anywhere(
-            &needles.iter().map(|x| Path::new(x)).collect::<Vec<_>>(),
+            &needles.iter().map(Path::new).collect::<Vec<_>>(),
             &data,
             true,
         );
@@ -209,7 +259,7 @@ mod tests {
             assert_eq!(
                 results
                     .iter()
-                    .any(|x| x.0 == &v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
+                    .any(|x| *x.0 == v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
                 v.1
             );
         }
@@ -231,7 +281,7 @@ mod tests {
             test_set.iter().map(|x| (x.0.iter().collect(), 10.0f32)),
         ));
         let results = match_consecutive(
-            &needles.iter().map(|x| Path::new(x)).collect::<Vec<_>>(),
+            &needles.iter().map(Path::new).collect::<Vec<_>>(),
             &data,
             true,
         );
@@ -242,7 +292,7 @@ mod tests {
             assert_eq!(
                 results
                     .iter()
-                    .any(|x| x.0 == &v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
+                    .any(|x| *x.0 == v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
                 v.1
             );
         }
@@ -261,7 +311,7 @@ mod tests {
             test_set.iter().map(|x| (x.0.iter().collect(), 10.0f32)),
         ));
         let results = match_fuzzy(
-            &needles.iter().map(|x| Path::new(x)).collect::<Vec<_>>(),
+            &needles.iter().map(Path::new).collect::<Vec<_>>(),
             &data,
             true,
             None,
@@ -273,9 +323,41 @@ mod tests {
             assert_eq!(
                 results
                     .iter()
-                    .any(|x| x.0 == &v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
+                    .any(|x| *x.0 == v.0.iter().collect::<PathBuf>() && x.1 == 10.0f32),
                 v.1
             );
         }
     }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_match_non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/foo/b\xe4r"));
+        let data = Database::from(HashMap::from_iter(vec![(path.clone(), 10.0f32)]));
+        let needle = Path::new(OsStr::from_bytes(b"\xe4r"));
+
+        assert_eq!(
+            match_anywhere(&[needle], &data, true),
+            vec![(path.as_path(), 10.0)]
+        );
+        assert_eq!(
+            match_consecutive(&[needle], &data, false),
+            vec![(path.as_path(), 10.0)]
+        );
+        assert!(match_anywhere(&[Path::new("\u{fffd}")], &data, true).is_empty());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"/foo/*", b"/foo/bar/baz"));
+        assert!(glob_match(b"*/ba?", b"/foo/bar"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"/foo/*/baz*", b"/foo/bar/baz"));
+        assert!(!glob_match(b"/foo/*/baz", b"/foo/baz"));
+        assert!(!glob_match(b"/foo", b"/foo/bar"));
+        assert!(!glob_match(b"?", b""));
+    }
 }
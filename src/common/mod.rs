@@ -1,4 +1,8 @@
 pub mod config;
+pub mod exists;
+pub mod index;
 pub mod r#match;
 pub mod opts;
+pub mod output;
 pub mod utils;
+pub mod weighting;
@@ -1,3 +1,13 @@
+use crate::common::config::Profile;
+use crate::common::output::{OutputFormat, OUTPUT_FORMATS};
+use crate::common::weighting::{WeightEdit, WEIGHT_EDITS};
+use crate::completion::{CompleteFormat, COMPLETE_FORMATS};
+use crate::dedupe::{DedupeKeep, DEDUPE_KEEPS};
+use crate::history::{HistoryShell, HISTORY_SHELLS};
+use crate::init::{Hook, Shell, HOOKS, SHELLS};
+use crate::merge::{MergeStrategy, PathMap, MERGE_STRATEGIES};
+use crate::stats::{StatsFormat, StatsSort, STATS_FORMATS, STATS_SORTS};
+use std::ffi::OsString;
 use std::path::PathBuf;
 use structopt::StructOpt;
 // use std::ffi::OsStr;
@@ -14,8 +24,36 @@ fn toggle_bool(i: u64) -> bool {
     i > 0
 }
 
+const COMMANDS: &[&str] = &[
+    "init", "import", "reweight", "weight", "edit", "stats", "purge", "dedupe", "move", "merge",
+    "daemon",
+];
+/// The options of `CommandOpts` which can come before the command, and if they
+/// take a value.
+const GLOBAL_OPTIONS: &[(&str, bool)] = &[
+    ("--verbose", false),
+    ("--profile", true),
+    ("--data-dir", true),
+    ("--output", true),
+];
+
 /// Jump to any directory fast and smart
 #[derive(StructOpt)]
+#[structopt(after_help = "COMMANDS:
+    init      Print the shell integration code, see `fastjump init --help`
+    import    Import the directories from a shell history, see `fastjump import --help`
+    reweight  Recompute the weights after changing FASTJUMP_WEIGHTING
+    weight    Edit the weights of any paths, see `fastjump weight --help`
+    edit      Edit the database in $EDITOR
+    stats     Report the statistics of the database, see `fastjump stats --help`
+    purge     Remove the directories which are gone, see `fastjump purge --help`
+    dedupe    Merge the entries of the same directories reached through symlinks
+    move      Move the entries under a directory which has been moved
+    merge     Merge another database into this one, see `fastjump merge --help`
+    daemon    Hold the database in memory to serve the prompts and jumps faster
+
+To jump to a directory named like a command, end the options with `--`, eg.
+`fastjump -- stats`, as the shell integration always does.")]
 pub struct Opts {
     /// The directory to jump to, composed of parts of a path
     // pub paths: Option<Vec<String>>,
@@ -37,15 +75,258 @@ pub struct Opts {
     /// Used for tab completion
     #[structopt(long, parse(from_occurrences = toggle_bool))]
     pub complete: bool,
-    /// Remove non-existent paths from database
+    /// The output format of tab completion
+    #[structopt(long, value_name = "format", default_value = "plain", possible_values = COMPLETE_FORMATS)]
+    pub complete_format: CompleteFormat,
+    /// Remove non-existent paths from database, see `fastjump purge` for more
     #[structopt(long, parse(from_occurrences = toggle_bool))]
     pub purge: bool,
-    /// Show database entries and their weights
+    /// Show database entries and their weights, see `fastjump stats` for more
     #[structopt(short, long, parse(from_occurrences = toggle_bool))]
     pub stat: bool,
     /// Dry run
     #[structopt(long, parse(from_occurrences = toggle_bool))]
     pub dryrun: bool,
+    /// Terminate output entries with NUL instead of newline
+    #[structopt(long, parse(from_occurrences = toggle_bool))]
+    pub print0: bool,
+    /// Use the separate database of the profile, eg. work [env: FASTJUMP_PROFILE]
+    #[structopt(long, value_name = "name")]
+    pub profile: Option<Profile>,
+    /// The directory of the databases [env: FASTJUMP_DATA_DIR]
+    #[structopt(long, value_name = "directory", parse(from_os_str))]
+    pub data_dir: Option<PathBuf>,
+    /// Print the results on stdout as JSON or tab-separated lines, for scripts
+    #[structopt(long, value_name = "format", possible_values = OUTPUT_FORMATS)]
+    pub output: Option<OutputFormat>,
+}
+
+/// Manage the database and the shell integration
+///
+/// The commands are parsed apart from `Opts` so that they never get mixed up
+/// with the directories to jump to, eg. `fastjump -- init` jumps to `init`.
+#[derive(StructOpt)]
+#[structopt(name = "fastjump")]
+pub struct CommandOpts {
+    /// Verbose mode (-v, -vv, -vvv, etc.)
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    pub verbose: u32,
+    /// Use the separate database of the profile, eg. work [env: FASTJUMP_PROFILE]
+    #[structopt(long, global = true, value_name = "name")]
+    pub profile: Option<Profile>,
+    /// The directory of the databases [env: FASTJUMP_DATA_DIR]
+    #[structopt(long, global = true, value_name = "directory", parse(from_os_str))]
+    pub data_dir: Option<PathBuf>,
+    /// Print the results on stdout as JSON or tab-separated lines, for scripts
+    #[structopt(long, global = true, value_name = "format", possible_values = OUTPUT_FORMATS)]
+    pub output: Option<OutputFormat>,
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+impl CommandOpts {
+    /// Checks if the arguments run a command rather than jump, i.e. the first
+    /// argument after the global options is a command. `--` ends the options,
+    /// so that `fastjump -- init` jumps to `init`.
+    pub fn is_command(args: &[OsString]) -> bool {
+        let mut args = args.iter().skip(1).map(|x| x.to_string_lossy());
+        while let Some(arg) = args.next() {
+            if let Some((_, value)) = GLOBAL_OPTIONS.iter().find(|x| x.0 == arg) {
+                if *value {
+                    args.next();
+                }
+                continue;
+            }
+            let inline_value = GLOBAL_OPTIONS
+                .iter()
+                .any(|(name, value)| *value && arg.starts_with(&format!("{}=", name)));
+            // -v, -vv, etc.
+            let verbose =
+                arg.len() > 1 && arg.starts_with('-') && arg[1..].bytes().all(|x| x == b'v');
+            if !inline_value && !verbose {
+                return COMMANDS.contains(&arg.as_ref());
+            }
+        }
+        false
+    }
+}
+
+#[derive(StructOpt)]
+pub enum Command {
+    /// Print the shell integration code, eg. eval "$(fastjump init bash)"
+    Init {
+        /// The shell to integrate with
+        #[structopt(possible_values = SHELLS)]
+        shell: Shell,
+        /// The name of the jump command, the other commands are named after it
+        #[structopt(long, value_name = "name", default_value = "j")]
+        cmd: String,
+        /// Record the current directory on every prompt or on directory changes
+        #[structopt(long, value_name = "style", possible_values = HOOKS)]
+        hook: Option<Hook>,
+    },
+    /// Import the directories changed to in the history of a shell
+    Import {
+        /// The shell which wrote the history
+        #[structopt(possible_values = HISTORY_SHELLS)]
+        shell: HistoryShell,
+        /// The history file, the default one of the shell if omitted
+        #[structopt(parse(from_os_str))]
+        file: Option<PathBuf>,
+        /// Dry run
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Recompute the weights by the weighting chosen by FASTJUMP_WEIGHTING
+    ///
+    /// The weightings are autojump (the default), linear and ema. The weights
+    /// are converted as if the entries had been visited the same number of
+    /// times with the new weighting.
+    Reweight {
+        /// Dry run
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Edit the weights of any paths, eg. fastjump weight scale 0.5 -p '/tmp/*'
+    ///
+    /// set and add create the entries of paths not in the database yet, sub
+    /// and scale only edit existing ones. The weights never go below zero.
+    Weight {
+        /// How to edit the weights
+        #[structopt(possible_values = WEIGHT_EDITS)]
+        op: WeightEdit,
+        /// The operand of the edit
+        value: f32,
+        /// The paths to edit
+        #[structopt(required = true, parse(from_os_str))]
+        targets: Vec<PathBuf>,
+        /// Match the entries by glob patterns instead, where `*` matches any
+        /// characters and `?` a single one
+        #[structopt(short, long, parse(from_occurrences = toggle_bool))]
+        pattern: bool,
+        /// Show the weights before and after without saving them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Report the entries and their weights, the heaviest first
+    Stats {
+        /// Report this many entries at most
+        #[structopt(short = "n", long, value_name = "count")]
+        top: Option<usize>,
+        /// Only the entries matching the glob pattern, eg. '~/src/*'
+        #[structopt(short, long, value_name = "pattern", parse(from_os_str))]
+        pattern: Option<PathBuf>,
+        /// Only the entries under the directory
+        #[structopt(long, value_name = "path", parse(from_os_str))]
+        prefix: Option<PathBuf>,
+        /// How to order the entries
+        #[structopt(long, value_name = "order", default_value = "weight", possible_values = STATS_SORTS)]
+        sort: StatsSort,
+        /// Reverse the order
+        #[structopt(short, long, parse(from_occurrences = toggle_bool))]
+        reverse: bool,
+        /// Aggregate the entries per top-level directory
+        #[structopt(short, long, parse(from_occurrences = toggle_bool))]
+        group: bool,
+        /// Show the home directory in full rather than as ~
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        no_abbrev: bool,
+        /// The output format
+        #[structopt(long, value_name = "format", default_value = "text", possible_values = STATS_FORMATS)]
+        format: StatsFormat,
+    },
+    /// Remove the entries of the directories which are gone
+    ///
+    /// The entries under the mount points which are not mounted at the moment,
+    /// eg. of an unplugged disk, and the ones which can't be checked for lack
+    /// of permission or a disconnected network file system are kept.
+    Purge {
+        /// Ask before removing the entries
+        #[structopt(short, long, parse(from_occurrences = toggle_bool))]
+        interactive: bool,
+        /// Remove the entries under the mount points which are not mounted too
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        unmounted: bool,
+        /// List the entries to remove without removing them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Merge the entries of the same directories reached through symlinks
+    ///
+    /// Their weights are combined as if all of the visits had been made to one
+    /// of them. Set FASTJUMP_CANONICAL=true to record the directories by their
+    /// paths with the symlinks resolved from then on.
+    Dedupe {
+        /// Which path to keep, canonical in canonical mode or heaviest otherwise
+        #[structopt(long, value_name = "path", possible_values = DEDUPE_KEEPS)]
+        keep: Option<DedupeKeep>,
+        /// List the entries to merge without merging them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Move the entries under a directory which has been moved or renamed,
+    /// eg. fastjump move ~/src ~/code
+    ///
+    /// The entries which end up with the same path are merged, combining their
+    /// weights as if all of the visits had been made to one of them.
+    Move {
+        /// The old path of the directory
+        #[structopt(parse(from_os_str))]
+        from: PathBuf,
+        /// The new path of the directory
+        #[structopt(parse(from_os_str))]
+        to: PathBuf,
+        /// List the entries to move without moving them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Merge another database into this one, eg. the one of another machine
+    ///
+    /// The file is either a database or a text file of `weight<TAB>path`
+    /// lines as listed by `fastjump edit`, or autojump's autojump.txt. The
+    /// entries not in this database are added as they are.
+    Merge {
+        /// The database or the text file to merge
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// How to combine the weights of the entries in both: the larger one,
+        /// the one of all of their visits, or their average
+        #[structopt(long, value_name = "strategy", default_value = "max", possible_values = MERGE_STRATEGIES)]
+        strategy: MergeStrategy,
+        /// How much the other weights count for in the average, from 0 to 1
+        #[structopt(long, value_name = "ratio", default_value = "0.5")]
+        ratio: f32,
+        /// Replace a path prefix of the other entries, eg. /home/me=/Users/me
+        #[structopt(short, long, value_name = "OLD=NEW", number_of_values = 1)]
+        map: Vec<PathMap>,
+        /// List the entries to add and change without saving them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
+    /// Hold the database in memory and serve the prompts, jumps and tab
+    /// completions on a socket, eg. fastjump daemon &
+    ///
+    /// The changes are saved periodically and on exit. Without a daemon
+    /// running, fastjump loads and saves the database file every time. The
+    /// daemon weighs the visits by its own FASTJUMP_WEIGHTING.
+    Daemon {
+        /// How often the changes are saved, in seconds
+        #[structopt(long, value_name = "seconds", default_value = "60")]
+        interval: u64,
+        /// Stop the running daemon instead, saving its changes
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        stop: bool,
+    },
+    /// Edit the database in $VISUAL or $EDITOR, one `weight<TAB>path` per line
+    ///
+    /// Deleting a line removes the entry, changing a weight updates it, and
+    /// changing a path renames the entry. The edit is only applied if all of
+    /// the lines are valid.
+    Edit {
+        /// Show the changes without saving them
+        #[structopt(long, parse(from_occurrences = toggle_bool))]
+        dryrun: bool,
+    },
 }
 
 /// Install/Uninstall necessary files.
@@ -83,4 +364,37 @@ pub struct InstallOpts {
     /// Verbose mode (-v, -vv, -vvv, etc.)
     #[structopt(short, long, parse(from_occurrences))]
     pub verbose: u32,
+    /// Only remove the database of the profile with --purge [env: FASTJUMP_PROFILE]
+    #[structopt(long, value_name = "name")]
+    pub profile: Option<Profile>,
+    /// The directory of the databases to remove with --purge [env: FASTJUMP_DATA_DIR]
+    #[structopt(long, value_name = "directory", parse(from_os_str))]
+    pub data_dir: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_command() {
+        let is_command = |args: &[&str]| {
+            let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+            CommandOpts::is_command(&args)
+        };
+        assert!(is_command(&["fastjump", "init", "bash"]));
+        assert!(is_command(&[
+            "fastjump",
+            "--profile",
+            "work",
+            "init",
+            "bash"
+        ]));
+        assert!(is_command(&["fastjump", "-vv", "--data-dir=/tmp", "stats"]));
+        assert!(!is_command(&["fastjump", "--", "init"]));
+        assert!(!is_command(&["fastjump", "--print0", "--", "stats"]));
+        assert!(!is_command(&["fastjump", "--profile", "init"]));
+        assert!(!is_command(&["fastjump", "foo"]));
+        assert!(!is_command(&["fastjump"]));
+    }
 }
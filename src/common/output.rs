@@ -0,0 +1,74 @@
+use crate::common::utils::print_bytes;
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const OUTPUT_FORMATS: &[&str] = &["text", "json", "tsv"];
+
+/// The format of the results of the commands on stdout.
+///
+/// With `Json` every command prints a single JSON object or array, the paths
+/// which are not valid UTF-8 with their raw bytes too, see `path_bytes`, and
+/// with `Tsv` one line per result with the fields separated by tabs and the
+/// path last, written byte for byte.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// As the shell integration expects, with the details logged on stderr
+    #[default]
+    Text,
+    Json,
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(format!("unknown output format {}", s)),
+        }
+    }
+}
+
+/// Prints the value as JSON on a line of stdout.
+pub fn print_json(value: &impl Serialize) -> Result<()> {
+    print_bytes(&[serde_json::to_string(value)?.as_bytes()], false)
+}
+
+/// Returns the raw bytes of the path if it's not valid UTF-8, as its string in
+/// JSON is then lossy, for a `path_bytes` field next to `path`.
+pub fn path_bytes(path: &Path) -> Option<Vec<u8>> {
+    match path.to_str() {
+        Some(_) => None,
+        None => Some(path.as_os_str().as_encoded_bytes().to_vec()),
+    }
+}
+
+/// Returns the JSON object with the path set as `key`, and its raw bytes as
+/// `<key>_bytes` if it's not valid UTF-8, see `path_bytes`.
+pub fn with_path(mut object: Value, key: &str, path: &Path) -> Value {
+    if let Value::Object(map) = &mut object {
+        map.insert(key.to_string(), path.to_string_lossy().into());
+        if let Some(bytes) = path_bytes(path) {
+            map.insert(format!("{}_bytes", key), bytes.into());
+        }
+    }
+    object
+}
+
+/// Prints the fields and the path separated by tabs on a line of stdout, or
+/// terminated with a NUL if `print0` is set.
+pub fn print_tsv(fields: &[String], path: &Path, print0: bool) -> Result<()> {
+    let mut parts: Vec<&[u8]> = vec![];
+    for field in fields {
+        parts.push(field.as_bytes());
+        parts.push(b"\t");
+    }
+    parts.push(path.as_os_str().as_encoded_bytes());
+    print_bytes(&parts, print0)
+}
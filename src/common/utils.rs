@@ -1,5 +1,6 @@
-use crate::common::opts::Opts;
+use crate::common::exists::first_existing;
 use crate::common::r#match::{match_anywhere, match_consecutive, match_fuzzy};
+use crate::completion::{format_menu_item, CompleteFormat};
 use crate::database::Database;
 #[cfg(target_family = "unix")]
 use anyhow::bail;
@@ -8,11 +9,12 @@ use const_format::concatcp;
 use lazy_static::lazy_static;
 use log::LevelFilter;
 use log::{debug, info};
-use path_clean::PathClean;
 use std::cmp::Ordering;
 use std::fmt::Display;
+use std::io::Write;
 use std::iter::Iterator;
 use std::path::{Component, Path, PathBuf, Prefix};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const PKGNAME: &str = env!("CARGO_PKG_NAME");
 
@@ -82,21 +84,17 @@ pub fn into_level(verbose: u32) -> log::LevelFilter {
     }
 }
 
-pub fn setup_logger(opts: &Opts) {
+pub fn setup_logger(verbose: u32) {
     let mut builder = env_logger::builder();
     #[cfg(not(debug_assertions))]
     let builder = builder.format_timestamp(None).format_module_path(false);
     builder
-        .filter_level(into_level(log::LevelFilter::Info as u32 + opts.verbose))
+        .filter_level(into_level(log::LevelFilter::Info as u32 + verbose))
         .parse_default_env()
         .init();
 }
 
 pub fn get_app_path() -> PathBuf {
-    if cfg!(test) {
-        return (*CWD).clone();
-    }
-
     #[cfg(target_os = "macos")]
     let data_home = shellexpand::tilde("~/Library");
     #[cfg(target_os = "windows")]
@@ -125,7 +123,7 @@ pub fn get_install_path() -> PathBuf {
 pub fn normalize_path(path: &Path) -> PathBuf {
     if let Some(Component::Prefix(prefix)) = path.components().next() {
         if let Prefix::Disk(drive) = prefix.kind() {
-            if (b'a'..=b'z').contains(&drive) {
+            if drive.is_ascii_lowercase() {
                 return path
                     .components()
                     .map(|x| {
@@ -142,68 +140,265 @@ pub fn normalize_path(path: &Path) -> PathBuf {
     path.components().collect()
 }
 
+/// Lexically resolves the `.` and `..` components of a path.
+///
+/// It works on the path components rather than on a string, so that paths
+/// which are not valid UTF-8 are kept as they are.
+pub fn clean_path(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match ret.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    ret.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => ret.push(component),
+            },
+            _ => ret.push(component),
+        }
+    }
+    if ret.as_os_str().is_empty() {
+        ret.push(Component::CurDir);
+    }
+    ret
+}
+
 pub fn absolute_path(path: impl AsRef<Path>) -> PathBuf {
+    absolute_path_from(&CWD, path)
+}
+
+/// Resolves a relative path against the working directory `cwd`, see
+/// `absolute_path`.
+pub fn absolute_path_from(cwd: &Path, path: impl AsRef<Path>) -> PathBuf {
     let path = path.as_ref();
 
-    if path.is_absolute() {
+    clean_path(&if path.is_absolute() {
         path.to_path_buf()
     } else {
-        (*CWD).join(path)
-    }
-    .clean()
+        cwd.join(path)
+    })
 }
 
 pub fn print_item<T: Display>((path, weight): (T, f32)) {
     info!("{:.2}\t\t{}", weight, path);
 }
 
+/// Checks if the needle is a part of the haystack, byte-wise.
+pub fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    let (first, rest) = match needle.split_first() {
+        Some(x) => x,
+        None => return true,
+    };
+    if haystack.len() < needle.len() {
+        return false;
+    }
+    haystack[..=haystack.len() - needle.len()]
+        .iter()
+        .enumerate()
+        .any(|(i, x)| x == first && haystack[i + 1..i + needle.len()] == *rest)
+}
+
+/// Returns the home directory of the user, if it can be found.
+pub fn home_dir() -> Option<PathBuf> {
+    let home = shellexpand::tilde("~");
+    if home == "~" {
+        None
+    } else {
+        Some(PathBuf::from(home.as_ref()))
+    }
+}
+
+/// Resolves the symlinks of a path, or returns it as it is if it can't be
+/// resolved, eg. it doesn't exist.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    match path.canonicalize() {
+        // drop the verbatim prefix of Windows, eg. `\\?\C:\`, which is not
+        // understood by the shells
+        #[cfg(target_os = "windows")]
+        Ok(x) => match x.to_str().and_then(|x| x.strip_prefix(r"\\?\")) {
+            Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+            _ => x,
+        },
+        #[cfg(not(target_os = "windows"))]
+        Ok(x) => x,
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Identifies the directory which a path resolves to, whichever symlinks it
+/// goes through.
+#[cfg(target_family = "unix")]
+pub type FileId = (u64, u64);
+#[cfg(not(target_family = "unix"))]
+pub type FileId = PathBuf;
+
+/// Returns the identity of the directory the path resolves to, if it exists.
+pub fn file_id(path: &Path) -> Option<FileId> {
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        path.metadata().ok().map(|x| (x.dev(), x.ino()))
+    }
+    #[cfg(not(target_family = "unix"))]
+    path.canonicalize().ok()
+}
+
+/// Abbreviates the home directory of the user at the start of the path to `~`.
+pub fn abbreviate_home(path: &Path) -> PathBuf {
+    match home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) => Path::new("~").join(rest),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Expands the `~` at the start of the path to the home directory of the user.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (home_dir(), path.strip_prefix("~")) {
+        (Some(home), Ok(rest)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Returns the current time in seconds since the epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+/// Converts a path into bytes to be stored.
+///
+/// Only unix can store the paths which are not valid UTF-8.
+pub fn path_to_bytes(path: &Path) -> Option<&[u8]> {
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(target_family = "unix"))]
+    path.to_str().map(|x| x.as_bytes())
+}
+
+/// Converts the bytes stored by `path_to_bytes` back into a path.
+pub fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(not(target_family = "unix"))]
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Writes a path to stdout as it is, terminated with a NUL if `print0` is set
+/// or with a newline otherwise.
+///
+/// The raw bytes are written so that paths which are not valid UTF-8 survive
+/// the round trip through the shell.
+pub fn print_path(path: &Path, print0: bool) -> Result<()> {
+    print_bytes(&[path.as_os_str().as_encoded_bytes()], print0)
+}
+
+/// Writes the concatenated parts to stdout, terminated with a NUL if `print0`
+/// is set or with a newline otherwise.
+pub fn print_bytes(parts: &[&[u8]], print0: bool) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for part in parts {
+        out.write_all(part)?;
+    }
+    out.write_all(if print0 { b"\0" } else { b"\n" })?;
+    out.flush()?;
+    Ok(())
+}
+
 /// Prints the tab completion menu according to the following format:
 /// ```text
 ///     [needle]__[index]__[possible_match]
 /// ```
 /// The needle (search pattern) and index are necessary to recreate the results
 /// on subsequent calls.
+///
+/// The items are the paths with their weights and last visited times.
 pub fn print_tab_menu<'a>(
-    needle: &'a str,
-    tab_entries: impl Iterator<Item = &'a (&'a Path, f32)>,
-    separator: &str,
-) {
+    needle: &'a [u8],
+    tab_entries: impl Iterator<Item = (&'a Path, f32, Option<u64>)>,
+    format: CompleteFormat,
+    print0: bool,
+) -> Result<()> {
     for (i, entry) in tab_entries.enumerate() {
         if !entry.0.as_os_str().is_empty() {
-            println!(
-                "{}{}{}{}{}",
-                needle,
-                separator,
-                i + 1,
-                separator,
-                entry.0.to_string_lossy()
-            );
+            print_bytes(&[&format_menu_item(format, needle, i + 1, entry)], print0)?;
         }
     }
+    Ok(())
 }
 
 /// If any needles contain an uppercase letter then use case sensitive
 /// searching. Otherwise use case insensitive searching.
 fn detect_smartcase(needles: &[&Path]) -> bool {
-    needles
-        .iter()
-        .any(|s| s.to_string_lossy().chars().any(|c| c.is_ascii_uppercase()))
+    needles.iter().any(|s| {
+        s.as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .any(|c| c.is_ascii_uppercase())
+    })
 }
 
 /// Return a vec containing matched result.
 ///
+/// With `existing` set to `Some(n)`, only the first `n` matches which exist
+/// are returned, see `find_existing_matches`.
+///
+/// The working directory `cwd` is left out of the results.
+///
 /// Will return `[("".to_string(), 0.0)]` avoid get error in the caller if
 /// 1. if found no matched result
 /// 2. if needles is empty
 pub fn find_matches<'a>(
     data: &'a Database,
+    cwd: &Path,
     needles: &[&Path],
-    check_existence: bool,
+    existing: Option<usize>,
 ) -> Vec<(&'a Path, f32)> {
+    match existing {
+        Some(count) => find_existing_matches(data, cwd, needles, count).0,
+        None => rank_matches(data, cwd, needles),
+    }
+}
+
+/// Returns the first `count` matches which exist, see `first_existing`, and
+/// the ones found missing before them.
+pub fn find_existing_matches<'a>(
+    data: &'a Database,
+    cwd: &Path,
+    needles: &[&Path],
+    count: usize,
+) -> (Vec<(&'a Path, f32)>, Vec<&'a Path>) {
+    let ranked = rank_matches(data, cwd, needles);
+    if ranked.first().is_some_and(|x| x.0 == Path::new(".")) {
+        return (ranked, vec![]);
+    }
+    let (found, missing) = first_existing(ranked.iter().map(|x| x.0), count);
+    let mut ret: Vec<_> = found
+        .into_iter()
+        .filter_map(|path| ranked.iter().find(|x| x.0 == path).copied())
+        .collect();
+    if ret.is_empty() {
+        ret.push((Path::new("."), 0.0));
+    }
+    (ret, missing)
+}
+
+/// Returns all of the matches, the best first, or `.` if there is none.
+fn rank_matches<'a>(data: &'a Database, cwd: &Path, needles: &[&Path]) -> Vec<(&'a Path, f32)> {
     if needles.is_empty() {
         return vec![(Path::new("."), 0.0)];
     }
-    if let Some(needle) = needles.get(0) {
+    if let Some(needle) = needles.first() {
         if needle.as_os_str().is_empty() {
             return vec![(Path::new("."), 0.0)];
         }
@@ -211,18 +406,12 @@ pub fn find_matches<'a>(
 
     let ignore_case = !detect_smartcase(needles);
 
-    let path_exists = if check_existence {
-        |path: &Path| path.exists()
-    } else {
-        |_: &Path| true
-    };
-
     let sort = |a: &(&'a Path, f32), b: &(&'a Path, f32)| {
         let weight =
             b.1.partial_cmp(&a.1)
                 .expect("can't compare the two float numbers");
         if weight == Ordering::Equal {
-            b.0.cmp(&a.0)
+            b.0.cmp(a.0)
         } else {
             weight
         }
@@ -242,9 +431,9 @@ pub fn find_matches<'a>(
 
     let mut ret: Vec<(&'a Path, f32)> = match_1
         .into_iter()
-        .chain(match_2.into_iter())
-        .chain(match_3.into_iter())
-        .filter(|(path, _)| *path != *CWD && path_exists(path))
+        .chain(match_2)
+        .chain(match_3)
+        .filter(|(path, _)| *path != cwd)
         .collect();
     debug!("=> match results: {:?}", ret);
 
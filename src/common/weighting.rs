@@ -0,0 +1,225 @@
+use std::fmt;
+use std::str::FromStr;
+
+pub const WEIGHTINGS: &[&str] = &["autojump", "linear", "ema"];
+pub const WEIGHT_EDITS: &[&str] = &["set", "add", "sub", "scale"];
+
+/// The smoothing factor of the exponential moving average
+const EMA_ALPHA: f32 = 0.25;
+/// The weight the exponential moving average converges to when visited
+const EMA_TARGET: f32 = 100.0;
+/// The weight the estimated visits are capped at, as the average never
+/// reaches its target
+const EMA_MAX_RATIO: f32 = 0.999;
+
+/// How the weights of the entries change when they are visited.
+///
+/// The weighting is chosen by `FASTJUMP_WEIGHTING`, and the database records
+/// which one its weights were computed by, so that they can be recomputed
+/// when switching to another one.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Weighting {
+    /// `sqrt(old² + 10²)` on visits and `old - 15` on decreases, as autojump
+    #[default]
+    Autojump,
+    /// The number of visits, `old + 1` on visits and `old - 1` on decreases
+    Linear,
+    /// An exponential moving average towards 100 on visits, so that the
+    /// recently visited entries outweigh the ones visited a lot long ago
+    Ema,
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "autojump" => Ok(Weighting::Autojump),
+            "linear" => Ok(Weighting::Linear),
+            "ema" => Ok(Weighting::Ema),
+            _ => Err(format!("unknown weighting {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Weighting::Autojump => "autojump",
+            Weighting::Linear => "linear",
+            Weighting::Ema => "ema",
+        })
+    }
+}
+
+impl Weighting {
+    /// The weight of a visit, unless given explicitly eg. by `--increase`.
+    pub fn default_increase(self) -> f32 {
+        match self {
+            Weighting::Autojump => 10.0,
+            Weighting::Linear => 1.0,
+            Weighting::Ema => EMA_TARGET,
+        }
+    }
+
+    /// The weight of a decrease, unless given explicitly by `--decrease`.
+    pub fn default_decrease(self) -> f32 {
+        match self {
+            Weighting::Autojump | Weighting::Ema => 15.0,
+            Weighting::Linear => 1.0,
+        }
+    }
+
+    /// Returns the weight after a visit.
+    pub fn increase(self, old: f32, weight: Option<f32>) -> f32 {
+        let weight = weight.unwrap_or_else(|| self.default_increase());
+        match self {
+            Weighting::Autojump => (old.powf(2.0) + weight.powf(2.0)).sqrt(),
+            Weighting::Linear => old + weight,
+            // an explicit weight scales the step towards the target, so that
+            // a visit never decreases the weight
+            Weighting::Ema => {
+                let step = (EMA_ALPHA * weight / EMA_TARGET).min(1.0);
+                (old + step * (EMA_TARGET - old)).max(old)
+            }
+        }
+    }
+
+    /// Returns the weight after a decrease.
+    pub fn decrease(self, old: f32, weight: Option<f32>) -> f32 {
+        (old - weight.unwrap_or_else(|| self.default_decrease())).max(0.0)
+    }
+
+    /// Returns the weight of an entry after the number of visits.
+    fn weight(self, visits: f32) -> f32 {
+        match self {
+            Weighting::Autojump => self.default_increase() * visits.sqrt(),
+            Weighting::Linear => self.default_increase() * visits,
+            Weighting::Ema => EMA_TARGET * (1.0 - (1.0 - EMA_ALPHA).powf(visits)),
+        }
+    }
+
+    /// Estimates the number of visits which result in the weight, the inverse
+    /// of `weight`.
+    fn visits(self, weight: f32) -> f32 {
+        let weight = weight.max(0.0);
+        match self {
+            Weighting::Autojump => (weight / self.default_increase()).powf(2.0),
+            Weighting::Linear => weight / self.default_increase(),
+            Weighting::Ema => {
+                let ratio = (weight / EMA_TARGET).min(EMA_MAX_RATIO);
+                (1.0 - ratio).ln() / (1.0 - EMA_ALPHA).ln()
+            }
+        }
+    }
+
+    /// Converts a weight computed by this weighting into the one computed by
+    /// another weighting for the same number of visits.
+    pub fn convert(self, weight: f32, to: Weighting) -> f32 {
+        if self == to {
+            return weight;
+        }
+        to.weight(self.visits(weight))
+    }
+
+    /// Merges the weights of two entries of the same directory, as if all of
+    /// their visits had been made to one entry.
+    pub fn merge(self, a: f32, b: f32) -> f32 {
+        self.weight(self.visits(a) + self.visits(b))
+    }
+}
+
+/// An edit of the weights by hand, regardless of the weighting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WeightEdit {
+    Set,
+    Add,
+    Sub,
+    Scale,
+}
+
+impl FromStr for WeightEdit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "set" => Ok(WeightEdit::Set),
+            "add" => Ok(WeightEdit::Add),
+            "sub" => Ok(WeightEdit::Sub),
+            "scale" => Ok(WeightEdit::Scale),
+            _ => Err(format!("unknown weight edit {}", s)),
+        }
+    }
+}
+
+impl WeightEdit {
+    /// Returns the edited weight, which is never negative.
+    pub fn apply(self, old: f32, value: f32) -> f32 {
+        match self {
+            WeightEdit::Set => value,
+            WeightEdit::Add => old + value,
+            WeightEdit::Sub => old - value,
+            WeightEdit::Scale => old * value,
+        }
+        .max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn visit(weighting: Weighting, times: usize) -> f32 {
+        (0..times).fold(0.0, |weight, _| weighting.increase(weight, None))
+    }
+
+    #[test]
+    fn test_increase() {
+        assert_eq!(Weighting::Autojump.increase(0.0, None), 10.0);
+        assert!((Weighting::Autojump.increase(10.0, None) - 14.142).abs() < 0.001);
+        assert_eq!(Weighting::Linear.increase(2.0, None), 3.0);
+        assert_eq!(Weighting::Ema.increase(0.0, None), 25.0);
+        assert_eq!(Weighting::Ema.increase(100.0, None), 100.0);
+        assert!(Weighting::Ema.increase(80.0, Some(5.0)) >= 80.0);
+        assert!(Weighting::Ema.increase(80.0, Some(200.0)) > Weighting::Ema.increase(80.0, None));
+        assert_eq!(Weighting::Ema.increase(80.0, Some(1000.0)), 100.0);
+        assert_eq!(Weighting::Ema.increase(150.0, None), 150.0);
+        assert_eq!(Weighting::Autojump.decrease(10.0, None), 0.0);
+        assert_eq!(Weighting::Linear.decrease(10.0, Some(3.0)), 7.0);
+    }
+
+    #[test]
+    fn test_weight_edit() {
+        assert_eq!(WeightEdit::Set.apply(10.0, 3.0), 3.0);
+        assert_eq!(WeightEdit::Add.apply(10.0, 3.0), 13.0);
+        assert_eq!(WeightEdit::Sub.apply(10.0, 30.0), 0.0);
+        assert_eq!(WeightEdit::Scale.apply(10.0, 0.5), 5.0);
+    }
+
+    #[test]
+    fn test_merge() {
+        for weighting in WEIGHTINGS {
+            let weighting: Weighting = weighting.parse().unwrap();
+            let merged = weighting.merge(visit(weighting, 2), visit(weighting, 3));
+            assert!((merged - visit(weighting, 5)).abs() < 0.01, "{}", weighting);
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        for from in WEIGHTINGS {
+            let from: Weighting = from.parse().unwrap();
+            for to in WEIGHTINGS {
+                let to: Weighting = to.parse().unwrap();
+                let converted = from.convert(visit(from, 5), to);
+                assert!(
+                    (converted - visit(to, 5)).abs() < 0.01,
+                    "{} => {}: {}",
+                    from,
+                    to,
+                    converted
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use crate::common::config::Config;
+use crate::common::utils::{abbreviate_home, path_from_bytes, path_to_bytes, unix_time};
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read};
+use std::io::Write;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::str::FromStr;
+use tempfile::NamedTempFile;
+
+pub const TAB_SEPARATOR: &str = "__";
+pub const COMPLETE_FORMATS: &[&str] = &["plain", "zsh", "fish"];
+
+/// How long the offered completion entries can be selected by index, in seconds.
+const CACHE_TTL: u64 = 60;
+/// How many completion menus are remembered at most, eg. for several shells.
+const CACHE_ENTRIES_COUNT: usize = 8;
+
+/// The output format of the completion entries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompleteFormat {
+    /// The bare entries
+    Plain,
+    /// `entry:description` pairs for `_describe` of zsh
+    Zsh,
+    /// `entry<TAB>description` pairs for `complete` of fish
+    Fish,
+}
+
+impl FromStr for CompleteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(CompleteFormat::Plain),
+            "zsh" => Ok(CompleteFormat::Zsh),
+            "fish" => Ok(CompleteFormat::Fish),
+            _ => Err(format!("unknown completion format {}", s)),
+        }
+    }
+}
+
+/// A command line argument in terms of the tab completion protocol.
+///
+/// A completion entry is offered as
+/// ```text
+///     [needle]__[index]__[path]
+/// ```
+/// where the path is the rest of the entry, so it can contain the separator.
+/// The needle is separated from the index at the first `__[digits]__` which is
+/// followed by an absolute or `~`-abbreviated path, or at the first
+/// `__[digits]` which ends the argument.
+#[derive(Debug, PartialEq)]
+pub enum TabEntry<'a> {
+    /// A complete entry as offered in the completion menu
+    Path {
+        needle: &'a [u8],
+        index: usize,
+        path: &'a [u8],
+    },
+    /// A needle followed by the index of an offered entry
+    Index { needle: &'a [u8], index: usize },
+    /// Not a completion entry but a plain needle
+    Needle(&'a [u8]),
+}
+
+/// Converts a part of an argument back into an `OsStr`.
+///
+/// Only unix can keep the bytes which are not valid UTF-8, see `path_from_bytes`.
+pub(crate) fn os_str(bytes: &[u8]) -> Cow<'_, OsStr> {
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Cow::Borrowed(OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(target_family = "unix"))]
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(x) => Cow::Borrowed(OsStr::new(x)),
+        Cow::Owned(x) => Cow::Owned(x.into()),
+    }
+}
+
+/// Parses an argument which may be a completion entry.
+pub fn parse_tab_entry(arg: &[u8]) -> TabEntry<'_> {
+    let separator = TAB_SEPARATOR.as_bytes();
+
+    for start in 0..arg.len() {
+        if !arg[start..].starts_with(separator) {
+            continue;
+        }
+        let digits = start + separator.len();
+        let end = digits
+            + arg[digits..]
+                .iter()
+                .take_while(|x| x.is_ascii_digit())
+                .count();
+        let index = match std::str::from_utf8(&arg[digits..end]).map(str::parse) {
+            Ok(Ok(index)) => index,
+            _ => continue,
+        };
+        let needle = &arg[..start];
+
+        if end == arg.len() {
+            return TabEntry::Index { needle, index };
+        }
+        if arg[end..].starts_with(separator) {
+            let path = &arg[end + separator.len()..];
+            let home = path
+                .strip_prefix(b"~")
+                .is_some_and(|x| x.is_empty() || x[0] == MAIN_SEPARATOR as u8);
+            if home || Path::new(&os_str(path)).is_absolute() {
+                return TabEntry::Path {
+                    needle,
+                    index,
+                    path,
+                };
+            }
+        }
+    }
+    TabEntry::Needle(arg)
+}
+
+/// Formats a completion entry, the index is 1-based.
+pub fn format_tab_entry(needle: &[u8], index: usize, path: &Path) -> Vec<u8> {
+    let mut entry = needle.to_vec();
+    entry.extend_from_slice(TAB_SEPARATOR.as_bytes());
+    entry.extend_from_slice(index.to_string().as_bytes());
+    entry.extend_from_slice(TAB_SEPARATOR.as_bytes());
+    entry.extend_from_slice(path.as_os_str().as_encoded_bytes());
+    entry
+}
+
+/// Formats how long ago the time was, roughly.
+fn format_age(time: u64, now: u64) -> String {
+    let age = now.saturating_sub(time);
+    match age {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+/// Formats a completion menu item in the given format, the index is 1-based.
+///
+/// The rich formats show the path `~`-abbreviated and describe the entry with
+/// its weight and when it was visited the last time.
+pub fn format_menu_item(
+    format: CompleteFormat,
+    needle: &[u8],
+    index: usize,
+    (path, weight, visited): (&Path, f32, Option<u64>),
+) -> Vec<u8> {
+    if format == CompleteFormat::Plain {
+        return format_tab_entry(needle, index, path);
+    }
+
+    let entry = format_tab_entry(needle, index, &abbreviate_home(path));
+    let mut description = format!("weight {:.1}", weight);
+    if let Some(visited) = visited {
+        description += &format!(", visited {}", format_age(visited, unix_time()));
+    }
+
+    let mut item = Vec::with_capacity(entry.len() + description.len() + 1);
+    match format {
+        CompleteFormat::Zsh => {
+            for c in entry {
+                if c == b':' || c == b'\\' {
+                    item.push(b'\\');
+                }
+                item.push(c);
+            }
+            item.push(b':');
+        }
+        _ => {
+            item.extend(entry);
+            item.push(b'\t');
+        }
+    }
+    item.extend_from_slice(description.as_bytes());
+    item
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedMenu {
+    created: u64,
+    /// All of the needles the menu was matched by, as they were given
+    needles: Vec<Vec<u8>>,
+    /// The offered paths at their positions, `None` if one can't be stored
+    paths: Vec<Option<Vec<u8>>>,
+}
+
+/// The recently offered completion menus.
+///
+/// It makes sure that a selected index resolves to exactly the path which was
+/// offered, even if the database has changed in between.
+#[derive(Serialize, Deserialize, Default)]
+pub struct CompletionCache {
+    menus: Vec<CachedMenu>,
+}
+
+impl CompletionCache {
+    /// Loads the cache, a missing or broken cache is just empty.
+    pub fn load(config: &Config) -> CompletionCache {
+        let mut cache: CompletionCache = read(&config.completion_path)
+            .ok()
+            .and_then(|x| bincode::deserialize(&x).ok())
+            .unwrap_or_default();
+        let now = unix_time();
+        cache
+            .menus
+            .retain(|x| x.created <= now && now - x.created < CACHE_TTL);
+        cache
+    }
+
+    /// Saves the cache through a temporary file, so that a shell completing
+    /// at the same time never reads it half written.
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let parent = config.completion_path.parent().unwrap_or(Path::new("."));
+        create_dir_all(parent)?;
+        let mut file = NamedTempFile::new_in(parent)?;
+        file.write_all(&bincode::serialize(self)?)?;
+        file.persist(&config.completion_path)?;
+        Ok(())
+    }
+
+    /// Remembers a menu offered for the needles, replacing the previous one.
+    pub fn insert<'a>(&mut self, needles: &[&[u8]], paths: impl Iterator<Item = &'a Path>) {
+        self.menus.retain(|x| x.needles != needles);
+        if self.menus.len() >= CACHE_ENTRIES_COUNT {
+            self.menus.remove(0);
+        }
+        self.menus.push(CachedMenu {
+            created: unix_time(),
+            needles: needles.iter().map(|x| x.to_vec()).collect(),
+            paths: paths
+                .map(|x| path_to_bytes(x).map(|x| x.to_vec()))
+                .collect(),
+        });
+    }
+
+    /// Finds the path offered for the needles at the 1-based index.
+    pub fn get(&self, needles: &[&[u8]], index: usize) -> Option<PathBuf> {
+        let menu = self.menus.iter().rev().find(|x| x.needles == needles)?;
+        let path = menu.paths.get(index.checked_sub(1)?)?.as_ref()?;
+        debug!(
+            "found {} in the completion cache",
+            String::from_utf8_lossy(path)
+        );
+        path_from_bytes(path.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tab_entry() {
+        let sep = std::path::MAIN_SEPARATOR;
+        let path = format!("{}foo__1__{}bar__baz", sep, sep);
+        let entry = format!("fo__2__{}", path);
+        assert_eq!(
+            parse_tab_entry(entry.as_bytes()),
+            TabEntry::Path {
+                needle: b"fo",
+                index: 2,
+                path: path.as_bytes()
+            }
+        );
+        assert_eq!(
+            parse_tab_entry(b"foo__bar__3"),
+            TabEntry::Index {
+                needle: b"foo__bar",
+                index: 3
+            }
+        );
+        assert_eq!(parse_tab_entry(b"foo__bar"), TabEntry::Needle(b"foo__bar"));
+        assert_eq!(
+            parse_tab_entry(b"foo__1__bar"),
+            TabEntry::Needle(b"foo__1__bar")
+        );
+        assert_eq!(parse_tab_entry(b"foo__"), TabEntry::Needle(b"foo__"));
+    }
+
+    #[test]
+    fn test_format_tab_entry() {
+        let sep = std::path::MAIN_SEPARATOR;
+        let path = PathBuf::from(format!("{}a__1__b{}c", sep, sep));
+        let entry = format_tab_entry(b"a", 1, &path);
+        assert_eq!(
+            parse_tab_entry(&entry),
+            TabEntry::Path {
+                needle: b"a",
+                index: 1,
+                path: path.as_os_str().as_encoded_bytes()
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_menu_item() {
+        let sep = std::path::MAIN_SEPARATOR;
+        let path = PathBuf::from(format!("{}a:b", sep));
+        let item = (path.as_path(), 12.345, None);
+
+        assert_eq!(
+            format_menu_item(CompleteFormat::Plain, b"a", 1, item),
+            format!("a__1__{}a:b", sep).into_bytes()
+        );
+        assert_eq!(
+            format_menu_item(CompleteFormat::Zsh, b"a", 1, item),
+            format!("a__1__{}a\\:b:weight 12.3", sep).into_bytes()
+        );
+        assert_eq!(
+            format_menu_item(CompleteFormat::Fish, b"a", 2, item),
+            format!("a__2__{}a:b\tweight 12.3", sep).into_bytes()
+        );
+        assert_eq!(format_age(1000, 1000 + 7200), "2h ago");
+    }
+
+    #[test]
+    fn test_cache() {
+        let mut cache = CompletionCache::default();
+        let paths = [PathBuf::from("foo"), PathBuf::from("bar")];
+        cache.insert(&[b"fo"], paths.iter().map(|x| x.as_path()));
+        cache.insert(&[b"a", b"fo"], paths[1..].iter().map(|x| x.as_path()));
+
+        assert_eq!(cache.get(&[b"fo"], 2), Some(PathBuf::from("bar")));
+        assert_eq!(cache.get(&[b"fo"], 0), None);
+        assert_eq!(cache.get(&[b"fo"], 3), None);
+        assert_eq!(cache.get(&[b"ba"], 1), None);
+        assert_eq!(cache.get(&[b"a", b"fo"], 1), Some(PathBuf::from("bar")));
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(None, Some(dir.path().to_path_buf()));
+        cache.save(&config).unwrap();
+        assert_eq!(
+            CompletionCache::load(&config).get(&[b"fo"], 1),
+            Some(PathBuf::from("foo"))
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use crate::common::config::Config;
+use crate::database::Database;
+use crate::handlers::{
+    add_entry, decrease_entry, existing_entries, forget_missing, match_entries, MatchEntry, Store,
+};
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{create_dir_all, remove_file, rename, set_permissions, Permissions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How long a request is waited for, so that a hung daemon doesn't hang the
+/// prompt, which falls back to the database file instead.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A path in the requests and responses, as its raw bytes, since the JSON
+/// strings can't hold the paths which are not valid UTF-8.
+type RawPath = Vec<u8>;
+
+fn to_raw(path: &Path) -> RawPath {
+    path.as_os_str().as_bytes().to_vec()
+}
+
+fn from_raw(raw: RawPath) -> PathBuf {
+    PathBuf::from(OsString::from_vec(raw))
+}
+
+/// A request to the daemon, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Add {
+        entry: RawPath,
+        weight: Option<f32>,
+    },
+    Decrease {
+        entry: RawPath,
+        weight: Option<f32>,
+    },
+    Matches {
+        cwd: RawPath,
+        needles: Vec<RawPath>,
+        existing: Option<usize>,
+    },
+    Jump {
+        cwd: RawPath,
+        needles: Vec<RawPath>,
+    },
+    /// Save the changes, before another process loads the database
+    Flush,
+    /// Save the changes and exit
+    Shutdown,
+}
+
+/// The response to a request, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    Weight(f32),
+    Matches(Vec<(RawPath, f32, Option<u64>)>),
+    Path(RawPath),
+    Done,
+    Error(String),
+}
+
+/// Identifies a version of the database file by its modification time, size
+/// and inode, the last of which changes on every save as the file is replaced.
+type FileStamp = (SystemTime, u64, u64);
+
+fn file_stamp(config: &Config) -> Option<FileStamp> {
+    let metadata = config.data_path.metadata().ok()?;
+    Some((metadata.modified().ok()?, metadata.len(), metadata.ino()))
+}
+
+fn as_paths(paths: &[PathBuf]) -> Vec<&Path> {
+    paths.iter().map(|x| x.as_path()).collect()
+}
+
+/// A change of the weights which is not saved yet, made again on the database
+/// if another process saves it meanwhile.
+enum Pending {
+    Add(PathBuf, Option<f32>),
+    Decrease(PathBuf, Option<f32>),
+}
+
+/// The database held in memory by the daemon.
+struct State {
+    data: Database,
+    /// If the database has changes which are not saved yet
+    dirty: bool,
+    /// The database file as of the last load or save
+    stamp: Option<FileStamp>,
+    /// The changes of the weights since then
+    pending: Vec<Pending>,
+}
+
+impl State {
+    fn load(config: &Config) -> Result<State> {
+        let mut data = Database::new(config)?;
+        // queried over and over, unlike by a single run
+        data.keep_index();
+        Ok(State {
+            data,
+            dirty: false,
+            stamp: file_stamp(config),
+            pending: vec![],
+        })
+    }
+
+    /// Reloads the database if another process has saved it since, eg. by
+    /// `fastjump edit`, with the changes which are not saved yet made again.
+    fn refresh(&mut self, config: &Config) -> Result<()> {
+        if file_stamp(config) == self.stamp {
+            return Ok(());
+        }
+        debug!(
+            "reloading the database, {} unsaved changes to make again",
+            self.pending.len()
+        );
+        let mut state = State::load(config)?;
+        for change in self.pending.drain(..) {
+            state.apply(config, change);
+        }
+        *self = state;
+        Ok(())
+    }
+
+    /// Changes the weight of an entry and returns its new weight.
+    fn apply(&mut self, config: &Config, change: Pending) -> f32 {
+        let weight = match &change {
+            Pending::Add(entry, weight) => add_entry(config, &mut self.data, entry, *weight),
+            Pending::Decrease(entry, weight) => {
+                decrease_entry(config, &mut self.data, entry, *weight)
+            }
+        };
+        self.pending.push(change);
+        self.dirty = true;
+        weight
+    }
+
+    /// Saves the changes, never over the ones of another process.
+    fn flush(&mut self, config: &Config) -> Result<()> {
+        self.refresh(config)?;
+        if self.dirty {
+            self.data.save(config)?;
+            self.dirty = false;
+            self.stamp = file_stamp(config);
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    /// Returns all of the matches of the needles, see `match_entries`.
+    fn ranked(&self, cwd: RawPath, needles: Vec<RawPath>) -> Vec<MatchEntry> {
+        let needles: Vec<_> = needles.into_iter().map(from_raw).collect();
+        match_entries(&self.data, &from_raw(cwd), &as_paths(&needles), None)
+    }
+}
+
+/// Locks the state, even if a request panicked while holding it.
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Locks the state with the changes of the other processes loaded.
+fn lock_fresh<'a>(config: &Config, state: &'a Mutex<State>) -> Result<MutexGuard<'a, State>> {
+    let mut state = lock(state);
+    state.refresh(config)?;
+    Ok(state)
+}
+
+/// Handles a request. The matches are checked for existence without holding
+/// the state, as it can take long, eg. on a hung mount.
+fn handle(config: &Config, state: &Mutex<State>, request: Request) -> Result<Response> {
+    Ok(match request {
+        Request::Add { entry, weight } => {
+            let change = Pending::Add(from_raw(entry), weight);
+            Response::Weight(lock_fresh(config, state)?.apply(config, change))
+        }
+        Request::Decrease { entry, weight } => {
+            let change = Pending::Decrease(from_raw(entry), weight);
+            Response::Weight(lock_fresh(config, state)?.apply(config, change))
+        }
+        Request::Matches {
+            cwd,
+            needles,
+            existing,
+        } => {
+            let ranked = lock_fresh(config, state)?.ranked(cwd, needles);
+            let entries = match existing {
+                Some(count) => existing_entries(ranked, count).0,
+                None => ranked,
+            };
+            Response::Matches(
+                entries
+                    .into_iter()
+                    .map(|(path, weight, visited)| (to_raw(&path), weight, visited))
+                    .collect(),
+            )
+        }
+        Request::Jump { cwd, needles } => {
+            let ranked = lock_fresh(config, state)?.ranked(cwd, needles);
+            let (found, missing) = existing_entries(ranked, 1);
+            let path = &found[0].0; // never empty
+            let mut state = lock_fresh(config, state)?;
+            state.dirty |= forget_missing(config, &mut state.data, path, &missing);
+            Response::Path(to_raw(path))
+        }
+        Request::Flush | Request::Shutdown => {
+            lock(state).flush(config)?;
+            Response::Done
+        }
+    })
+}
+
+/// Serves the requests of a connection until it's closed, and returns if the
+/// daemon is asked to shut down.
+fn serve_client(config: &Config, state: &Mutex<State>, stream: UnixStream) -> Result<bool> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let request: Request = serde_json::from_str(&line?)?;
+        debug!("{:?}", request);
+        let shutdown = matches!(request, Request::Shutdown);
+        let response =
+            handle(config, state, request).unwrap_or_else(|e| Response::Error(e.to_string()));
+        writer.write_all(format!("{}\n", serde_json::to_string(&response)?).as_bytes())?;
+        if shutdown {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Saves the changes and removes the socket.
+fn shutdown(config: &Config, state: &Mutex<State>) {
+    if let Err(e) = lock(state).flush(config) {
+        warn!("failed to save the database: {}", e);
+    }
+    let _ = remove_file(&config.socket_path);
+    info!("The daemon has stopped.");
+}
+
+/// Holds the database in memory and serves the requests on the socket until
+/// it's asked to shut down or terminated, saving the changes every `interval`
+/// and on exit.
+pub fn serve(config: Config, interval: Duration) -> Result<()> {
+    Daemon::bind(config)?.run(interval, true)
+}
+
+/// A daemon listening on its socket, see `serve`.
+pub struct Daemon {
+    config: Arc<Config>,
+    state: Arc<Mutex<State>>,
+    listener: UnixListener,
+}
+
+impl Daemon {
+    /// Loads the database and binds the socket, so that the clients can
+    /// connect as soon as it returns.
+    pub fn bind(config: Config) -> Result<Daemon> {
+        if UnixStream::connect(&config.socket_path).is_ok() {
+            bail!(
+                "the daemon is already running on {}",
+                config.socket_path.display()
+            );
+        }
+        // the socket of a daemon which didn't exit cleanly
+        let _ = remove_file(&config.socket_path);
+        create_dir_all(&config.data_dir)?;
+        let state = State::load(&config)?;
+        // bound in a private directory and only moved in place once no one
+        // else can connect to it
+        let parent = config.socket_path.parent().unwrap_or(Path::new("."));
+        let private = tempfile::Builder::new()
+            .prefix(".fastjump-")
+            .tempdir_in(parent)?;
+        set_permissions(private.path(), Permissions::from_mode(0o700))?;
+        let path = private.path().join("socket");
+        let listener =
+            UnixListener::bind(&path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        set_permissions(&path, Permissions::from_mode(0o600))?;
+        rename(&path, &config.socket_path)
+            .map_err(|e| anyhow!("{}: {}", config.socket_path.display(), e))?;
+        Ok(Daemon {
+            config: Arc::new(config),
+            state: Arc::new(Mutex::new(state)),
+            listener,
+        })
+    }
+
+    /// Serves the requests until the daemon is asked to shut down, saving the
+    /// changes every `interval`. With `signals`, it also saves them and exits
+    /// on SIGINT and SIGTERM, which only one daemon of the process can do.
+    pub fn run(self, interval: Duration, signals: bool) -> Result<()> {
+        let Daemon {
+            config,
+            state,
+            listener,
+        } = self;
+        {
+            let (config, state) = (config.clone(), state.clone());
+            thread::spawn(move || loop {
+                thread::sleep(interval);
+                if let Err(e) = lock(&state).flush(&config) {
+                    warn!("failed to save the database: {}", e);
+                }
+            });
+        }
+        if signals {
+            let (config, state) = (config.clone(), state.clone());
+            ctrlc::set_handler(move || {
+                shutdown(&config, &state);
+                std::process::exit(0);
+            })?;
+        }
+        info!(
+            "The daemon is listening on {}.",
+            config.socket_path.display()
+        );
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        for stream in listener.incoming() {
+            if stopping.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(x) => x,
+                Err(e) => {
+                    debug!("failed to accept a client: {}", e);
+                    continue;
+                }
+            };
+            let (config, state, stopping) = (config.clone(), state.clone(), stopping.clone());
+            thread::spawn(move || match serve_client(&config, &state, stream) {
+                Ok(true) => {
+                    stopping.store(true, Ordering::SeqCst);
+                    // wake up the listener to stop
+                    let _ = UnixStream::connect(&config.socket_path);
+                }
+                Ok(false) => {}
+                Err(e) => debug!("failed to serve a client: {}", e),
+            });
+        }
+        shutdown(&config, &state);
+        Ok(())
+    }
+}
+
+/// Asks the daemon, if it's running, to save its changes before another
+/// process loads the database.
+pub fn flush(config: &Config) {
+    if let Some(mut client) = Client::connect(config) {
+        if let Err(e) = client.request(&Request::Flush) {
+            warn!("failed to save the changes of the daemon: {}", e);
+        }
+    }
+}
+
+/// Asks the daemon to save its changes and exit.
+pub fn stop(config: &Config) -> Result<()> {
+    let mut client = Client::connect(config).ok_or_else(|| anyhow!("the daemon is not running"))?;
+    client.request(&Request::Shutdown)?;
+    Ok(())
+}
+
+/// A connection to the daemon, see `serve`.
+pub struct Client {
+    stream: BufReader<UnixStream>,
+}
+
+impl Client {
+    /// Connects to the daemon of the database, if it's running.
+    pub fn connect(config: &Config) -> Option<Client> {
+        let stream = UnixStream::connect(&config.socket_path).ok()?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+        Some(Client {
+            stream: BufReader::new(stream),
+        })
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Response> {
+        let line = format!("{}\n", serde_json::to_string(request)?);
+        self.stream.get_mut().write_all(line.as_bytes())?;
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            bail!("the daemon closed the connection");
+        }
+        match serde_json::from_str(&line)? {
+            Response::Error(e) => bail!(e),
+            response => Ok(response),
+        }
+    }
+}
+
+fn unexpected(response: Response) -> anyhow::Error {
+    anyhow!("unexpected response of the daemon {:?}", response)
+}
+
+impl Store for Client {
+    fn add(&mut self, _: &Config, entry: &Path, weight: Option<f32>) -> Result<f32> {
+        let entry = to_raw(entry);
+        match self.request(&Request::Add { entry, weight })? {
+            Response::Weight(x) => Ok(x),
+            x => Err(unexpected(x)),
+        }
+    }
+
+    fn decrease(&mut self, _: &Config, entry: &Path, weight: Option<f32>) -> Result<f32> {
+        let entry = to_raw(entry);
+        match self.request(&Request::Decrease { entry, weight })? {
+            Response::Weight(x) => Ok(x),
+            x => Err(unexpected(x)),
+        }
+    }
+
+    fn matches(
+        &mut self,
+        cwd: &Path,
+        needles: &[&Path],
+        existing: Option<usize>,
+    ) -> Result<Vec<MatchEntry>> {
+        let request = Request::Matches {
+            cwd: to_raw(cwd),
+            needles: needles.iter().map(|x| to_raw(x)).collect(),
+            existing,
+        };
+        match self.request(&request)? {
+            Response::Matches(x) => Ok(x
+                .into_iter()
+                .map(|(path, weight, visited)| (from_raw(path), weight, visited))
+                .collect()),
+            x => Err(unexpected(x)),
+        }
+    }
+
+    fn jump(&mut self, _: &Config, cwd: &Path, needles: &[&Path]) -> Result<PathBuf> {
+        let request = Request::Jump {
+            cwd: to_raw(cwd),
+            needles: needles.iter().map(|x| to_raw(x)).collect(),
+        };
+        match self.request(&request)? {
+            Response::Path(x) => Ok(from_raw(x)),
+            x => Err(unexpected(x)),
+        }
+    }
+
+    /// The daemon saves the changes on its own.
+    fn flush(&mut self, _: &Config) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(None, Some(dir.path().to_path_buf()));
+        let daemon = Daemon::bind(Config::new(None, Some(dir.path().to_path_buf()))).unwrap();
+        let server = thread::spawn(move || daemon.run(Duration::from_secs(3600), false));
+        let mut client = Client::connect(&config).unwrap();
+        let mode = config.socket_path.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let entry = dir.path().join("a");
+        let weight = client.add(&config, &entry, Some(3.0)).unwrap();
+        assert_eq!(weight, config.weighting.increase(0.0, Some(3.0)));
+        let matches = client
+            .matches(Path::new("/"), &[Path::new("a")], None)
+            .unwrap();
+        assert_eq!(matches[0].0, entry);
+        assert!(matches[0].2.is_some());
+        // not saved until flushed
+        assert!(Database::new(&config).unwrap().is_empty());
+
+        flush(&config);
+        assert_eq!(Database::new(&config).unwrap().get(&entry), Some(&weight));
+
+        // changed by another process meanwhile
+        let mut data = Database::new(&config).unwrap();
+        data.insert(dir.path().join("b"), 1.0);
+        data.save(&config).unwrap();
+        let matches = client
+            .matches(Path::new("/"), &[Path::new("b")], None)
+            .unwrap();
+        assert_eq!(matches[0].0, dir.path().join("b"));
+
+        // and again while the daemon has unsaved changes, kept on both sides
+        let entry_d = dir.path().join("d");
+        let weight_d = client.add(&config, &entry_d, None).unwrap();
+        let mut data = Database::new(&config).unwrap();
+        data.insert(dir.path().join("e"), 2.0);
+        data.save(&config).unwrap();
+        flush(&config);
+        let data = Database::new(&config).unwrap();
+        assert_eq!(data.get(&dir.path().join("e")), Some(&2.0));
+        assert_eq!(data.get(&entry_d), Some(&weight_d));
+        assert_eq!(data.get(&entry), Some(&weight));
+
+        // not valid UTF-8
+        let entry_c = dir.path().join(OsStr::from_bytes(b"c\xff"));
+        create_dir_all(&entry_c).unwrap();
+        client.add(&config, &entry_c, None).unwrap();
+        let needle = Path::new(OsStr::from_bytes(b"c\xff"));
+        let path = client.jump(&config, Path::new("/"), &[needle]).unwrap();
+        assert_eq!(path, entry_c);
+
+        client.add(&config, &entry, None).unwrap();
+        stop(&config).unwrap();
+        server.join().unwrap().unwrap();
+        assert!(!config.socket_path.exists());
+        assert!(Database::new(&config).unwrap()[&entry] > weight);
+    }
+}
@@ -1,21 +1,244 @@
 use crate::common::config::Config;
-use anyhow::{bail, Result};
-use std::collections::HashMap;
+use crate::common::index::MatchIndex;
+use crate::common::utils::{home_dir, path_from_bytes, path_to_bytes, unix_time};
+use crate::common::weighting::Weighting;
+use crate::purge::mount_point;
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use serde::de::{Error as DeError, Visitor};
+use serde::ser::Error as SerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::{copy, create_dir_all, read, rename};
 use std::io::{BufWriter, Write};
 use std::ops::{Deref, DerefMut};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 use tempfile::NamedTempFile;
 
 const BACKUP_THRESHOLD: u64 = 24 * 60 * 60;
+/// The header of the versioned databases.
+pub const MAGIC: &[u8] = b"FJDB";
+const VERSION: u32 = 2;
+/// The prefix of the paths stored relative to the home directory.
+const HOME_PREFIX: &str = "~";
+
+/// Returns the path under the home directory relative to it, eg. `~/src`, so
+/// that the database still works after being copied to another machine or
+/// user with another home directory.
+fn relative_to_home<'a>(path: &'a Path, home: Option<&Path>) -> Cow<'a, Path> {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => Cow::Borrowed(Path::new(HOME_PREFIX)),
+        Some(rest) => Cow::Owned(Path::new(HOME_PREFIX).join(rest)),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Expands a path stored by `relative_to_home` with the home directory.
+fn expand_from_home(path: PathBuf, home: Option<&Path>) -> PathBuf {
+    match (home, path.strip_prefix(HOME_PREFIX)) {
+        (Some(home), Ok(rest)) if rest.as_os_str().is_empty() => home.to_path_buf(),
+        (Some(home), Ok(rest)) => home.join(rest),
+        _ => path,
+    }
+}
+
+/// Returns the home directory to store the paths relative to, unless it's
+/// the root, eg. for a system user.
+fn portable_home() -> Option<PathBuf> {
+    home_dir().filter(|x| x.is_absolute() && x.parent().is_some())
+}
+
+/// Serializes a path as raw bytes, as serde refuses to serialize a path which
+/// is not valid UTF-8.
+///
+/// The bincode layout is the same as a string's, so that the existing
+/// databases can still be loaded.
+struct RawPath<'a>(&'a Path);
+
+impl Serialize for RawPath<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match path_to_bytes(self.0) {
+            Some(path) => serializer.serialize_bytes(path),
+            None => Err(S::Error::custom("path contains invalid UTF-8 characters")),
+        }
+    }
+}
+
+/// Deserializes a path serialized by `RawPath`.
+#[derive(PartialEq, Eq, Hash)]
+struct RawPathBuf(PathBuf);
+
+struct RawPathVisitor;
+
+impl<'de> Visitor<'de> for RawPathVisitor {
+    type Value = RawPathBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a path")
+    }
+
+    fn visit_str<E: DeError>(self, v: &str) -> std::result::Result<Self::Value, E> {
+        Ok(RawPathBuf(PathBuf::from(v)))
+    }
+
+    fn visit_bytes<E: DeError>(self, v: &[u8]) -> std::result::Result<Self::Value, E> {
+        self.visit_byte_buf(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: DeError>(self, v: Vec<u8>) -> std::result::Result<Self::Value, E> {
+        path_from_bytes(v)
+            .map(RawPathBuf)
+            .ok_or_else(|| E::custom("path contains invalid UTF-8 characters"))
+    }
+}
+
+impl<'de> Deserialize<'de> for RawPathBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_byte_buf(RawPathVisitor)
+    }
+}
+
+/// A map of the paths, serialized with `RawPath` keys.
+struct RawMap<'a, V>(Vec<(Cow<'a, Path>, &'a V)>);
+
+impl<'a, V> RawMap<'a, V> {
+    /// Collects the entries, with the paths under the home directory stored
+    /// relative to it.
+    fn new(entries: impl IntoIterator<Item = (&'a PathBuf, &'a V)>, home: Option<&Path>) -> Self {
+        RawMap(
+            entries
+                .into_iter()
+                .map(|(k, v)| (relative_to_home(k, home), v))
+                .collect(),
+        )
+    }
+}
+
+impl<V: Serialize> Serialize for RawMap<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (RawPath(k), v)))
+    }
+}
+
+/// The weights of the paths as deserialized.
+type RawWeights = HashMap<RawPathBuf, f32>;
+/// The times of the paths as deserialized, in seconds since the epoch.
+type RawTimes = HashMap<RawPathBuf, u64>;
+
+fn from_raw_map<V>(data: HashMap<RawPathBuf, V>, home: Option<&Path>) -> HashMap<PathBuf, V> {
+    data.into_iter()
+        .map(|(k, v)| (expand_from_home(k.0, home), v))
+        .collect()
+}
+
+/// Deserializes a database, either a versioned one or the original plain map
+/// of the weights without any header, and returns its version.
+///
+/// Version 2 added the last visits, the weighting, the missing entries and the
+/// mount points, and stores the paths under the home directory relative to
+/// it.
+fn deserialize_data(bytes: &[u8], home: Option<&Path>) -> Result<(Database, u32)> {
+    let (version, body) = match bytes.strip_prefix(MAGIC) {
+        Some(rest) if rest.len() >= 4 => {
+            let mut version = [0; 4];
+            version.copy_from_slice(&rest[..4]);
+            (u32::from_le_bytes(version), &rest[4..])
+        }
+        _ => (1, bytes),
+    };
+    debug!("loading the database of version {}", version);
+
+    let data = match version {
+        1 => {
+            let weights: RawWeights = bincode::deserialize(body)?;
+            Database::from(from_raw_map(weights, None))
+        }
+        2 => {
+            let (weights, visited, weighting, missing, mounts): (
+                RawWeights,
+                RawTimes,
+                String,
+                RawTimes,
+                Vec<RawPathBuf>,
+            ) = bincode::deserialize(body)?;
+            let mut data = Database::from(from_raw_map(weights, home));
+            data.visited = from_raw_map(visited, home);
+            data.weighting = weighting.parse().map_err(|e: String| anyhow!(e))?;
+            data.missing = from_raw_map(missing, home);
+            data.mounts = mounts
+                .into_iter()
+                .map(|x| expand_from_home(x.0, home))
+                .collect();
+            data
+        }
+        _ => bail!("unsupported database version {}", version),
+    };
+    Ok((data, version))
+}
+
+/// Serializes a database with the current version, with the paths under the
+/// home directory relative to it.
+fn serialize_data(data: &Database, home: Option<&Path>) -> Result<Vec<u8>> {
+    let weights = RawMap::new(data.data.iter(), home);
+    // keep the metadata of the existing entries only
+    let visited = RawMap::new(
+        data.visited
+            .iter()
+            .filter(|(k, _)| data.data.contains_key(*k)),
+        home,
+    );
+    let missing = RawMap::new(
+        data.missing
+            .iter()
+            .filter(|(k, _)| data.data.contains_key(*k)),
+        home,
+    );
+    // keep the mount points which have entries under them only
+    let mounts: Vec<_> = data
+        .mounts
+        .iter()
+        .filter(|x| data.data.keys().any(|k| k.starts_with(x)))
+        .map(|x| relative_to_home(x, home))
+        .collect();
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&bincode::serialize(&(
+        weights,
+        visited,
+        data.weighting.to_string(),
+        missing,
+        mounts.iter().map(|x| RawPath(x)).collect::<Vec<_>>(),
+    ))?);
+    Ok(bytes)
+}
 
 pub struct Database {
     data: HashMap<PathBuf, f32>,
+    /// When the paths were visited the last time, in seconds since the epoch
+    visited: HashMap<PathBuf, u64>,
+    /// How the weights have been computed
+    weighting: Weighting,
+    /// When the paths were found missing the first time, in seconds since the
+    /// epoch, until they are found again or forgotten
+    missing: HashMap<PathBuf, u64>,
+    /// The mount points of the file systems the entries were added on, other
+    /// than the one of the root
+    mounts: HashSet<PathBuf>,
+    index: OnceCell<MatchIndex>,
+    /// Build the trigram table along with the index, see `keep_index`
+    trigrams: bool,
 }
 
 impl DerefMut for Database {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        // any modification invalidates the index, see `set_weight` for the
+        // one which doesn't
+        self.index.take();
         &mut self.data
     }
 }
@@ -30,7 +253,15 @@ impl Deref for Database {
 
 impl From<HashMap<PathBuf, f32>> for Database {
     fn from(data: HashMap<PathBuf, f32>) -> Self {
-        Database { data }
+        Database {
+            data,
+            visited: HashMap::new(),
+            weighting: Weighting::default(),
+            missing: HashMap::new(),
+            mounts: HashSet::new(),
+            index: OnceCell::new(),
+            trigrams: false,
+        }
     }
 }
 
@@ -44,12 +275,30 @@ impl From<HashMap<PathBuf, f32>> for Database {
 impl Database {
     fn load_data(config: &Config) -> Result<Database> {
         if !config.data_path.exists() {
-            Ok(Database {
-                data: HashMap::new(),
-            })
+            Ok(Database::from(HashMap::new()))
         } else {
-            let hash = bincode::deserialize(&read(&config.data_path)?)?;
-            Ok(Database { data: hash })
+            let bytes = read(&config.data_path)?;
+            let (data, version) = deserialize_data(&bytes, portable_home().as_deref())?;
+            if version < VERSION {
+                data.migrate(config, version);
+            }
+            Ok(data)
+        }
+    }
+
+    /// Saves the database loaded from an older version with the current one
+    /// once, keeping the original as the backup, so that eg. its paths are
+    /// relative to the home directory from then on.
+    fn migrate(&self, config: &Config, version: u32) {
+        info!(
+            "migrating the database from version {} to {}",
+            version, VERSION
+        );
+        let result = copy(&config.data_path, &config.backup_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.save(config));
+        if let Err(e) = result {
+            warn!("failed to migrate the database: {}", e);
         }
     }
 
@@ -58,9 +307,18 @@ impl Database {
             rename(config.backup_path.as_path(), config.data_path.as_path())?;
             return Database::load_data(config);
         }
-        Ok(Database {
-            data: HashMap::new(),
-        })
+        Ok(Database::from(HashMap::new()))
+    }
+
+    /// Deserializes a database of any of the supported versions, eg. one
+    /// copied from another machine.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Database> {
+        Ok(deserialize_data(bytes, portable_home().as_deref())?.0)
+    }
+
+    /// Serializes the database with the current version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        serialize_data(self, portable_home().as_deref())
     }
 
     pub fn new(config: &Config) -> Result<Database> {
@@ -71,6 +329,170 @@ impl Database {
         }
     }
 
+    /// Returns when the path was visited the last time, in seconds since the
+    /// epoch, if it has been recorded.
+    pub fn visited(&self, path: &Path) -> Option<u64> {
+        self.visited.get(path).copied()
+    }
+
+    /// Records a visit of the path at the current time.
+    pub fn touch(&mut self, path: &Path) {
+        self.visited.insert(path.to_path_buf(), unix_time());
+        self.missing.remove(path);
+    }
+
+    /// Records that the path is missing at the time, unless it has been
+    /// missing since earlier. Returns if it wasn't recorded yet.
+    pub fn mark_missing(&mut self, path: &Path, time: u64) -> bool {
+        if self.missing.contains_key(path) {
+            return false;
+        }
+        self.missing.insert(path.to_path_buf(), time);
+        true
+    }
+
+    /// Forgets that the path was missing, returns if it was recorded.
+    pub fn clear_missing(&mut self, path: &Path) -> bool {
+        self.missing.remove(path).is_some()
+    }
+
+    /// Returns the paths which have been missing since before the time.
+    pub fn missing_since(&self, time: u64) -> Vec<PathBuf> {
+        self.missing
+            .iter()
+            .filter(|(path, since)| **since < time && self.data.contains_key(*path))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Records the mount point of the file system the path is on, unless it's
+    /// the one of the root, so that purge can tell the entries of an unplugged
+    /// disk from the removed ones.
+    pub fn record_mount(&mut self, path: &Path) {
+        if let Some(point) = mount_point(path) {
+            self.mounts.insert(point);
+        }
+    }
+
+    /// Returns the mount points recorded by `record_mount`.
+    pub fn mount_points(&self) -> impl Iterator<Item = &PathBuf> {
+        self.mounts.iter()
+    }
+
+    /// Sets when the path was visited the last time, or forgets it.
+    pub fn set_visited(&mut self, path: &Path, time: Option<u64>) {
+        match time {
+            Some(time) => self.visited.insert(path.to_path_buf(), time),
+            None => self.visited.remove(path),
+        };
+    }
+
+    /// Merges the entry of a path into the one of another, combining their
+    /// weights by the weighting and keeping the latest visit.
+    pub fn merge_entry(&mut self, from: &Path, into: &Path) {
+        if from == into {
+            return;
+        }
+        if let Some(entry) = self.take_entry(from) {
+            self.merge_into(into, entry);
+        }
+    }
+
+    /// Moves the entries of a path and of all of the paths under it to
+    /// another path, merging them with the entries already there. Returns the
+    /// paths moved and where to.
+    pub fn move_prefix(&mut self, from: &Path, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut moves: Vec<_> = self
+            .keys()
+            .filter_map(|path| {
+                let rest = path.strip_prefix(from).ok()?;
+                let target = if rest.as_os_str().is_empty() {
+                    to.to_path_buf()
+                } else {
+                    to.join(rest)
+                };
+                Some((path.clone(), target))
+            })
+            .filter(|(path, target)| path != target)
+            .collect();
+        moves.sort();
+
+        // take them all out first, as the targets can be under the paths
+        let entries: Vec<_> = moves
+            .iter()
+            .filter_map(|(path, _)| self.take_entry(path))
+            .collect();
+        for ((_, target), entry) in moves.iter().zip(entries) {
+            self.merge_into(target, entry);
+        }
+        moves
+    }
+
+    /// Removes the entry of a path, returning its weight and last visit.
+    fn take_entry(&mut self, path: &Path) -> Option<(f32, Option<u64>)> {
+        let weight = self.remove(path)?;
+        self.missing.remove(path);
+        Some((weight, self.visited.remove(path)))
+    }
+
+    /// Merges a weight and a visit into the entry of a path.
+    fn merge_into(&mut self, path: &Path, (weight, visited): (f32, Option<u64>)) {
+        let weight = match self.get(path) {
+            Some(x) => self.weighting.merge(*x, weight),
+            None => weight,
+        };
+        self.insert(path.to_path_buf(), weight);
+        let visited = visited.max(self.visited(path));
+        self.set_visited(path, visited);
+        self.missing.remove(path);
+    }
+
+    /// Returns how the weights have been computed.
+    pub fn weighting(&self) -> Weighting {
+        self.weighting
+    }
+
+    /// Recomputes all of the weights by another weighting, as if the entries
+    /// had been visited the same number of times with it.
+    pub fn reweight(&mut self, weighting: Weighting) {
+        let from = self.weighting;
+        for weight in self.values_mut() {
+            *weight = from.convert(*weight, weighting);
+        }
+        self.weighting = weighting;
+    }
+
+    /// Returns the match index of the entries, built on first use.
+    pub fn index(&self) -> &MatchIndex {
+        self.index.get_or_init(|| {
+            let index = MatchIndex::new(self.data.keys());
+            if self.trigrams {
+                index.build_trigrams();
+            }
+            index
+        })
+    }
+
+    /// Builds the index with its trigram table now and after every change of
+    /// the paths, for the databases which are held in memory and queried many
+    /// times, eg. by the daemon.
+    pub fn keep_index(&mut self) {
+        self.trigrams = true;
+        self.index.take();
+        self.index();
+    }
+
+    /// Sets the weight of the path, which keeps the index if the path has an
+    /// entry already.
+    pub fn set_weight(&mut self, path: &Path, weight: f32) {
+        match self.data.get_mut(path) {
+            Some(x) => *x = weight,
+            None => {
+                self.insert(path.to_path_buf(), weight);
+            }
+        }
+    }
+
     pub fn save(&self, config: &Config) -> Result<()> {
         let parent = config.data_path.parent();
         if let Some(path) = parent {
@@ -80,7 +502,7 @@ impl Database {
             let temp_file = NamedTempFile::new_in(path)?;
             let (temp_file, temp_file_path) = temp_file.keep()?;
             let mut buffer = BufWriter::new(temp_file);
-            buffer.write_all(&bincode::serialize(self.deref())?)?;
+            buffer.write_all(&self.to_bytes()?)?;
             buffer.flush()?;
             rename(temp_file_path, config.data_path.as_path())?;
 
@@ -101,11 +523,170 @@ impl Database {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
-    #[ignore]
     fn bincode_layout() {
-        assert_eq!(2, 1);
+        let path = PathBuf::from("/foo/bar");
+        let mut data = HashMap::new();
+        data.insert(path.clone(), 10.0f32);
+        let mut legacy = HashMap::new();
+        legacy.insert(path.to_str().unwrap().to_string(), 10.0f32);
+
+        let legacy = bincode::serialize(&legacy).unwrap();
+        assert_eq!(
+            bincode::serialize(&RawMap::new(data.iter(), None)).unwrap(),
+            legacy
+        );
+        assert_eq!(*deserialize_data(&legacy, None).unwrap().0, data);
+    }
+
+    #[test]
+    fn versioned_layout() {
+        let path = PathBuf::from("/foo/bar");
+        let mut data = Database::from(HashMap::new());
+        data.insert(path.clone(), 10.0f32);
+        data.touch(&path);
+        data.touch(Path::new("/removed"));
+        data.reweight(Weighting::Linear);
+        data.insert(PathBuf::from("/missing"), 1.0);
+        assert!(data.mark_missing(Path::new("/missing"), 42));
+        assert!(!data.mark_missing(Path::new("/missing"), 43));
+        data.mounts.insert(PathBuf::from("/foo"));
+        data.mounts.insert(PathBuf::from("/unused"));
+
+        let bytes = serialize_data(&data, None).unwrap();
+        assert!(bytes.starts_with(MAGIC));
+        let (loaded, version) = deserialize_data(&bytes, None).unwrap();
+        assert_eq!(version, VERSION);
+        assert_eq!(*loaded, *data);
+        assert_eq!(loaded.visited(&path), data.visited(&path));
+        assert_eq!(loaded.visited(Path::new("/removed")), None);
+        assert_eq!(loaded.weighting(), Weighting::Linear);
+        assert_eq!(loaded.missing_since(42), Vec::<PathBuf>::new());
+        assert_eq!(loaded.missing_since(43), [PathBuf::from("/missing")]);
+        assert_eq!(
+            loaded.mount_points().collect::<Vec<_>>(),
+            [&PathBuf::from("/foo")]
+        );
+    }
+
+    #[test]
+    fn migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(None, Some(dir.path().to_path_buf()));
+        let mut legacy = HashMap::new();
+        legacy.insert("/foo/bar".to_string(), 10.0f32);
+        let legacy = bincode::serialize(&legacy).unwrap();
+        std::fs::write(&config.data_path, &legacy).unwrap();
+
+        // the original is kept as the backup to downgrade with
+        let data = Database::new(&config).unwrap();
+        assert_eq!(data.get(Path::new("/foo/bar")), Some(&10.0));
+        assert_eq!(read(&config.backup_path).unwrap(), legacy);
+        assert!(read(&config.data_path).unwrap().starts_with(MAGIC));
+    }
+
+    #[test]
+    fn home_relative_paths() {
+        let home = Path::new("/home/a");
+        let mut data = Database::from(HashMap::new());
+        for path in ["/home/a", "/home/a/src", "/home/ab", "/tmp"] {
+            data.insert(PathBuf::from(path), 1.0);
+            data.touch(Path::new(path));
+        }
+
+        let bytes = serialize_data(&data, Some(home)).unwrap();
+        let (stored, _) = deserialize_data(&bytes, None).unwrap();
+        let mut paths: Vec<_> = stored.keys().map(|x| x.to_str().unwrap()).collect();
+        paths.sort();
+        assert_eq!(paths, ["/home/ab", "/tmp", "~", "~/src"]);
+        assert_eq!(
+            stored.visited(Path::new("~/src")),
+            data.visited(Path::new("/home/a/src"))
+        );
+
+        // on another machine
+        let (loaded, _) = deserialize_data(&bytes, Some(Path::new("/Users/a"))).unwrap();
+        assert!(loaded.contains_key(Path::new("/Users/a")));
+        assert!(loaded.contains_key(Path::new("/Users/a/src")));
+        assert!(loaded.contains_key(Path::new("/home/ab")));
+        assert_eq!(loaded.len(), 4);
+    }
+
+    #[test]
+    fn keep_index() {
+        let mut data = Database::from(HashMap::new());
+        data.insert(PathBuf::from("/foo"), 1.0);
+        data.keep_index();
+        // a new weight keeps the index, a new path doesn't
+        data.set_weight(Path::new("/foo"), 2.0);
+        assert!(data.index.get().is_some());
+        assert_eq!(data[Path::new("/foo")], 2.0);
+        data.set_weight(Path::new("/bar"), 1.0);
+        assert!(data.index.get().is_none());
+        assert_eq!(data.index().entries().len(), 2);
+    }
+
+    #[test]
+    fn merge_entry() {
+        let mut data = Database::from(HashMap::new());
+        data.reweight(Weighting::Linear);
+        data.insert(PathBuf::from("/a"), 2.0);
+        data.insert(PathBuf::from("/b"), 3.0);
+        data.set_visited(Path::new("/a"), Some(42));
+
+        data.merge_entry(Path::new("/a"), Path::new("/b"));
+        assert_eq!(data.get(Path::new("/a")), None);
+        assert_eq!(data.get(Path::new("/b")), Some(&5.0));
+        assert_eq!(data.visited(Path::new("/b")), Some(42));
+
+        data.merge_entry(Path::new("/b"), Path::new("/c"));
+        assert_eq!(data.get(Path::new("/c")), Some(&5.0));
+        assert_eq!(data.len(), 1);
+    }
+
+    #[test]
+    fn move_prefix() {
+        let mut data = Database::from(HashMap::new());
+        data.reweight(Weighting::Linear);
+        for (path, weight) in [("/a", 1.0), ("/a/b", 2.0), ("/a/b/b", 3.0), ("/ab", 4.0)] {
+            data.insert(PathBuf::from(path), weight);
+        }
+
+        let moves = data.move_prefix(Path::new("/a"), Path::new("/a/b"));
+        assert_eq!(moves.len(), 3);
+        let mut entries: Vec<_> = data
+            .iter()
+            .map(|(k, v)| (k.to_str().unwrap(), *v))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(
+            entries,
+            [
+                ("/a/b", 1.0),
+                ("/a/b/b", 2.0),
+                ("/a/b/b/b", 3.0),
+                ("/ab", 4.0)
+            ]
+        );
+
+        data.move_prefix(Path::new("/a/b/b"), Path::new("/a/b"));
+        assert_eq!(data.get(Path::new("/a/b")), Some(&3.0));
+        assert_eq!(data.get(Path::new("/a/b/b")), Some(&3.0));
+        assert_eq!(data.len(), 3);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut data = Database::from(HashMap::new());
+        data.insert(PathBuf::from(OsStr::from_bytes(b"/foo/b\xffr")), 10.0f32);
+
+        let bytes = serialize_data(&data, None).unwrap();
+        assert_eq!(*deserialize_data(&bytes, None).unwrap().0, *data);
     }
 }
@@ -0,0 +1,120 @@
+use crate::common::utils::{canonical_path, file_id, FileId};
+use crate::database::Database;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const DEDUPE_KEEPS: &[&str] = &["heaviest", "canonical", "shortest"];
+
+/// Which path of the duplicate entries is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DedupeKeep {
+    /// The one visited the most
+    Heaviest,
+    /// The one with the symlinks resolved, even if it's not recorded yet
+    Canonical,
+    /// The one with the fewest characters
+    Shortest,
+}
+
+impl FromStr for DedupeKeep {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "heaviest" => Ok(DedupeKeep::Heaviest),
+            "canonical" => Ok(DedupeKeep::Canonical),
+            "shortest" => Ok(DedupeKeep::Shortest),
+            _ => Err(format!("unknown path to keep {}", s)),
+        }
+    }
+}
+
+/// Finds the entries of the same directories, reached by symlinks or by
+/// their targets, and returns which paths are merged into which.
+///
+/// With `DedupeKeep::Canonical` the lone entries of symlinks are moved to
+/// their targets as well.
+pub fn find_duplicates(data: &Database, keep: DedupeKeep) -> Vec<(PathBuf, PathBuf)> {
+    let mut groups: HashMap<FileId, Vec<&PathBuf>> = HashMap::new();
+    for path in data.keys() {
+        if let Some(id) = file_id(path) {
+            groups.entry(id).or_default().push(path);
+        }
+    }
+    let mut groups: Vec<_> = groups.into_values().collect();
+    for paths in groups.iter_mut() {
+        paths.sort();
+    }
+    groups.sort();
+
+    let mut ret = vec![];
+    for paths in groups {
+        let into = match keep {
+            DedupeKeep::Heaviest => paths
+                .iter()
+                .max_by(|a, b| {
+                    data[**a]
+                        .partial_cmp(&data[**b])
+                        .unwrap_or(Ordering::Equal)
+                        // the first one of the equally heavy ones
+                        .then(b.cmp(a))
+                })
+                .map(|x| x.to_path_buf()),
+            DedupeKeep::Canonical => paths.first().map(|x| canonical_path(x)),
+            DedupeKeep::Shortest => paths
+                .iter()
+                .min_by_key(|x| x.as_os_str().len())
+                .map(|x| x.to_path_buf()),
+        };
+        let into = match into {
+            Some(x) => x,
+            None => continue,
+        };
+        ret.extend(
+            paths
+                .into_iter()
+                .filter(|x| **x != into)
+                .map(|x| (x.clone(), into.clone())),
+        );
+    }
+    ret
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_find_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = canonical_path(dir.path());
+        let target = dir.join("target");
+        let link = dir.join("link");
+        let lone = dir.join("lone");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::create_dir(dir.join("other")).unwrap();
+        symlink(&target, &link).unwrap();
+        symlink(dir.join("other"), &lone).unwrap();
+
+        let mut data = Database::from(HashMap::new());
+        data.insert(target.clone(), 1.0);
+        data.insert(link.clone(), 2.0);
+        data.insert(lone.clone(), 1.0);
+        data.insert(dir.join("missing"), 1.0);
+
+        assert_eq!(
+            find_duplicates(&data, DedupeKeep::Heaviest),
+            [(target.clone(), link.clone())]
+        );
+        assert_eq!(
+            find_duplicates(&data, DedupeKeep::Shortest),
+            [(target.clone(), link.clone())]
+        );
+        let mut merges = find_duplicates(&data, DedupeKeep::Canonical);
+        merges.sort();
+        assert_eq!(merges, [(link, target), (lone, dir.join("other"))]);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::common::utils::{
+    clean_path, expand_home, normalize_path, path_from_bytes, path_to_bytes,
+};
+use crate::database::Database;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use strsim::normalized_levenshtein;
+
+const HEADER: &str = "\
+# Edit the weights and the paths of the entries, one `weight<TAB>path` per line.
+# Deleting a line removes the entry, and a changed path renames the entry on
+# the line it replaces. The lines starting with `#` are ignored.
+";
+
+/// An entry listed for editing.
+pub type Entry = (PathBuf, f32);
+
+/// A change of the database made by editing the listed entries.
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Remove(PathBuf),
+    Update(PathBuf, f32),
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        weight: f32,
+    },
+    Add(PathBuf, f32),
+}
+
+/// Lists the entries to edit, the heaviest first, and their text.
+///
+/// The weights are written in full so that they are read back unchanged. The
+/// paths with a newline can't be edited on a line, so they are left out and
+/// kept as they are.
+pub fn dump(data: &Database) -> (Vec<Entry>, Vec<u8>) {
+    let mut entries: Vec<Entry> = data
+        .iter()
+        .filter(|(path, _)| !path.as_os_str().as_encoded_bytes().contains(&b'\n'))
+        .filter(|(path, _)| path_to_bytes(path).is_some())
+        .map(|(path, weight)| (path.clone(), *weight))
+        .collect();
+    entries.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.0.cmp(&b.0))
+    });
+
+    let mut text = HEADER.as_bytes().to_vec();
+    for (path, weight) in &entries {
+        text.extend_from_slice(format!("{}\t", weight).as_bytes());
+        text.extend_from_slice(path_to_bytes(path).unwrap_or_default());
+        text.push(b'\n');
+    }
+    (entries, text)
+}
+
+/// Parses the edited text into the entries, or all of the errors found.
+pub fn parse(text: &[u8]) -> Result<Vec<Entry>, Vec<String>> {
+    let mut entries = vec![];
+    let mut errors = vec![];
+    let mut seen = HashSet::new();
+
+    for (i, line) in text.split(|x| *x == b'\n').enumerate() {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.iter().all(u8::is_ascii_whitespace) || line.starts_with(b"#") {
+            continue;
+        }
+        let mut error = |message: String| errors.push(format!("line {}: {}", i + 1, message));
+
+        let (weight, path) = match line.iter().position(|x| *x == b'\t') {
+            Some(pos) => (&line[..pos], &line[pos + 1..]),
+            None => {
+                error("expected a weight and a path separated by a tab".to_string());
+                continue;
+            }
+        };
+        let weight = String::from_utf8_lossy(weight);
+        let weight = match weight.trim().parse::<f32>() {
+            Ok(x) if x.is_finite() && x >= 0.0 => x,
+            _ => {
+                error(format!("invalid weight {:?}", weight));
+                continue;
+            }
+        };
+        let path = match path_from_bytes(path.to_vec()) {
+            Some(x) if !x.as_os_str().is_empty() => clean_path(&normalize_path(&expand_home(&x))),
+            _ => {
+                error("invalid path".to_string());
+                continue;
+            }
+        };
+        if !path.is_absolute() {
+            error(format!("{} is not an absolute path", path.display()));
+            continue;
+        }
+        if !seen.insert(path.clone()) {
+            error(format!("{} is listed more than once", path.display()));
+            continue;
+        }
+        entries.push((path, weight));
+    }
+
+    if errors.is_empty() {
+        Ok(entries)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Compares the edited entries with the listed ones.
+///
+/// The entries are matched by their paths first, wherever their lines have
+/// been moved to. A new path is taken as a rename of one of the unmatched
+/// entries right after the same matched line as in the listing, the one with
+/// the most similar path, or as a new entry if there is none.
+pub fn diff(old: &[Entry], new: &[Entry]) -> Vec<Change> {
+    let old_index: HashMap<_, _> = old.iter().enumerate().map(|(i, x)| (&x.0, i)).collect();
+    let new_paths: HashSet<_> = new.iter().map(|x| &x.0).collect();
+    let mut claimed: Vec<bool> = old.iter().map(|x| new_paths.contains(&x.0)).collect();
+    let mut changes = vec![];
+
+    // where the unmatched entries after the last matched line start
+    let mut next = 0;
+    for (path, weight) in new {
+        if let Some(&i) = old_index.get(path) {
+            if old[i].1 != *weight {
+                changes.push(Change::Update(path.clone(), *weight));
+            }
+            next = i + 1;
+        } else if let Some(i) = (next..old.len())
+            .take_while(|i| !claimed[*i])
+            .max_by(|a, b| {
+                let similarity = |i: &usize| {
+                    normalized_levenshtein(&old[*i].0.to_string_lossy(), &path.to_string_lossy())
+                };
+                // the first one of the equally similar ones
+                similarity(a)
+                    .partial_cmp(&similarity(b))
+                    .unwrap_or(Ordering::Equal)
+                    .then(b.cmp(a))
+            })
+        {
+            claimed[i] = true;
+            changes.push(Change::Rename {
+                from: old[i].0.clone(),
+                to: path.clone(),
+                weight: *weight,
+            });
+            next = i + 1;
+        } else {
+            changes.push(Change::Add(path.clone(), *weight));
+        }
+    }
+
+    for (i, (path, _)) in old.iter().enumerate() {
+        if !claimed[i] {
+            changes.push(Change::Remove(path.clone()));
+        }
+    }
+    changes
+}
+
+/// Applies the changes to the database, moving along when the entries were
+/// visited.
+pub fn apply(data: &mut Database, changes: &[Change]) {
+    // remove first so that a path can be renamed to a removed one
+    for change in changes {
+        if let Change::Remove(path) = change {
+            data.remove(path);
+        }
+    }
+    let mut visited = vec![];
+    for change in changes {
+        if let Change::Rename { from, .. } = change {
+            visited.push(data.visited(from));
+            data.remove(from);
+        }
+    }
+
+    let mut visited = visited.into_iter();
+    for change in changes {
+        match change {
+            Change::Update(path, weight) | Change::Add(path, weight) => {
+                data.insert(path.clone(), *weight);
+            }
+            Change::Rename { to, weight, .. } => {
+                data.set_visited(to, visited.next().flatten());
+                data.insert(to.clone(), *weight);
+            }
+            Change::Remove(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn entries(items: &[(&str, f32)]) -> Vec<Entry> {
+        items.iter().map(|(p, w)| (PathBuf::from(p), *w)).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = b"# comment\n10\t/foo\n\n2.5\t/bar/../baz\t\r\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            entries(&[("/foo", 10.0), ("/baz\t", 2.5)])
+        );
+
+        let errors = parse(b"x\t/foo\n1 /foo\n1\tfoo\n1\t/foo\n1\t/foo/\n-1\t/bar\n").unwrap_err();
+        assert_eq!(errors.len(), 5);
+        assert!(errors[0].starts_with("line 1: invalid weight"));
+        assert!(errors[1].starts_with("line 2: expected"));
+        assert!(errors[2].starts_with("line 3: foo is not"));
+        assert!(errors[3].starts_with("line 5: /foo is listed"));
+        assert!(errors[4].starts_with("line 6: invalid weight"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = entries(&[("/a", 3.0), ("/b", 2.0), ("/c", 1.0), ("/d", 1.0)]);
+        let new = entries(&[
+            ("/d", 1.0),
+            ("/a", 3.0),
+            ("/x", 2.0),
+            ("/c", 5.0),
+            ("/y", 1.0),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Rename {
+                    from: PathBuf::from("/b"),
+                    to: PathBuf::from("/x"),
+                    weight: 2.0
+                },
+                Change::Update(PathBuf::from("/c"), 5.0),
+                Change::Add(PathBuf::from("/y"), 1.0),
+            ]
+        );
+
+        let new = entries(&[("/a", 3.0), ("/cc", 1.0)]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Rename {
+                    from: PathBuf::from("/c"),
+                    to: PathBuf::from("/cc"),
+                    weight: 1.0
+                },
+                Change::Remove(PathBuf::from("/b")),
+                Change::Remove(PathBuf::from("/d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut data = Database::from(HashMap::new());
+        data.insert(PathBuf::from("/a"), 1.0);
+        data.insert(PathBuf::from("/b"), 2.0);
+        data.set_visited(Path::new("/a"), Some(42));
+
+        let (old, text) = dump(&data);
+        assert_eq!(old, entries(&[("/b", 2.0), ("/a", 1.0)]));
+        let text = String::from_utf8(text).unwrap().replace("1\t/a", "4\t/aa");
+        let text = text.replace("2\t/b\n", "");
+        apply(&mut data, &diff(&old, &parse(text.as_bytes()).unwrap()));
+
+        assert_eq!(data.len(), 1);
+        assert_eq!(data.get(Path::new("/aa")), Some(&4.0));
+        assert_eq!(data.visited(Path::new("/aa")), Some(42));
+    }
+}
@@ -1,41 +1,273 @@
 use crate::common::config::Config;
+use crate::common::exists::first_existing;
+use crate::common::output::{print_json, print_tsv, with_path, OutputFormat};
+use crate::common::r#match::match_glob;
 use crate::common::utils::{
-    absolute_path, find_matches, normalize_path, print_item, print_tab_menu, CWD,
+    absolute_path_from, canonical_path, expand_home, find_existing_matches, find_matches,
+    normalize_path, print_bytes, print_item, print_path, print_tab_menu, unix_time, CWD,
 };
+use crate::common::weighting::WeightEdit;
+use crate::completion::{os_str, parse_tab_entry, CompleteFormat, CompletionCache, TabEntry};
 use crate::database::Database;
-use anyhow::{bail, Result};
-use log::info;
+use crate::dedupe::{find_duplicates, DedupeKeep};
+use crate::edit::{apply, diff, dump, parse, Change};
+use crate::history::{cd_targets, parse_history, CdTracker, HistoryShell};
+use crate::init::{init_script, Hook, Shell};
+use crate::merge::{load_source, merge_changes, MergeStrategy, PathMap};
+use crate::purge::{Mounts, PathState};
+use crate::stats::{format_json, format_text, stats_report, tsv_fields, StatsFormat, StatsOptions};
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use serde_json::json;
 use std::cmp::Ordering;
-use std::path::Path;
+use std::collections::HashSet;
+use std::fs::read;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
-const TAB_ENTRIES_COUNT: usize = 9;
-const TAB_SEPARATOR: &str = "__";
+/// Warns if the weights would be mixed up with the ones of another weighting.
+fn check_weighting(config: &Config, data: &Database) {
+    if data.weighting() != config.weighting && !data.is_empty() {
+        warn!(
+            "the database is weighted by {} rather than {}, please run `fastjump reweight`",
+            data.weighting(),
+            config.weighting
+        );
+    }
+}
+
+/// Prints the new weight of an entry, see `OutputFormat`.
+fn print_weight(config: &Config, entry: &Path, weight: f32) -> Result<()> {
+    match config.output {
+        OutputFormat::Text => {
+            print_item((entry.to_string_lossy(), weight));
+            Ok(())
+        }
+        OutputFormat::Json => print_json(&with_path(json!({ "weight": weight }), "path", entry)),
+        OutputFormat::Tsv => print_tsv(&[weight.to_string()], entry, false),
+    }
+}
+
+/// Prints the path to jump to, which is `null` in JSON if nothing matches.
+fn print_target(config: &Config, path: &Path, print0: bool) -> Result<()> {
+    match config.output {
+        OutputFormat::Json if path == Path::new(".") => print_json(&json!({ "path": null })),
+        OutputFormat::Json => print_json(&with_path(json!({}), "path", path)),
+        OutputFormat::Text | OutputFormat::Tsv => print_path(path, print0),
+    }
+}
+
+/// Prints the tab completion menu, see `print_tab_menu` for the text.
+fn print_menu(
+    config: &Config,
+    needle: &[u8],
+    entries: &[MatchEntry],
+    format: CompleteFormat,
+    print0: bool,
+) -> Result<()> {
+    // the placeholder of no match
+    let items = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.0 != Path::new("."))
+        .map(|(i, x)| (i + 1, x));
+    match config.output {
+        OutputFormat::Text => print_tab_menu(
+            needle,
+            entries.iter().map(|x| (x.0.as_path(), x.1, x.2)),
+            format,
+            print0,
+        ),
+        OutputFormat::Json => print_json(
+            &items
+                .map(|(index, (path, weight, visited))| {
+                    let item = json!({
+                        "index": index,
+                        "weight": weight,
+                        "visited": visited,
+                    });
+                    with_path(item, "path", path)
+                })
+                .collect::<Vec<_>>(),
+        ),
+        OutputFormat::Tsv => {
+            for (index, (path, weight, visited)) in items {
+                let visited = visited.map(|x| x.to_string()).unwrap_or_default();
+                print_tsv(
+                    &[index.to_string(), weight.to_string(), visited],
+                    path,
+                    print0,
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns the entry of a path relative to `cwd`, with the symlinks resolved
+/// in canonical mode.
+pub fn path_entry(config: &Config, cwd: &Path, path: &Path) -> PathBuf {
+    let entry = absolute_path_from(cwd, normalize_path(path));
+    if config.canonical {
+        canonical_path(&entry)
+    } else {
+        entry
+    }
+}
+
+/// A matched entry with its weight and the time of its last visit.
+pub type MatchEntry = (PathBuf, f32, Option<u64>);
+
+/// Where the entries are recorded and matched, either the database itself
+/// or the daemon holding it in memory.
+pub trait Store {
+    /// Records a visit of the entry and returns its new weight.
+    fn add(&mut self, config: &Config, entry: &Path, weight: Option<f32>) -> Result<f32>;
+    /// Decreases the weight of the entry and returns its new weight.
+    fn decrease(&mut self, config: &Config, entry: &Path, weight: Option<f32>) -> Result<f32>;
+    /// Returns the matches of the needles, see `find_matches`.
+    fn matches(
+        &mut self,
+        cwd: &Path,
+        needles: &[&Path],
+        existing: Option<usize>,
+    ) -> Result<Vec<MatchEntry>>;
+    /// Returns the best match which exists, see `jump_target`.
+    fn jump(&mut self, config: &Config, cwd: &Path, needles: &[&Path]) -> Result<PathBuf>;
+    /// Writes the changes through to the disk, if the store doesn't on its own.
+    fn flush(&mut self, config: &Config) -> Result<()>;
+}
+
+impl Store for Database {
+    fn add(&mut self, config: &Config, entry: &Path, weight: Option<f32>) -> Result<f32> {
+        Ok(add_entry(config, self, entry, weight))
+    }
+
+    fn decrease(&mut self, config: &Config, entry: &Path, weight: Option<f32>) -> Result<f32> {
+        Ok(decrease_entry(config, self, entry, weight))
+    }
+
+    fn matches(
+        &mut self,
+        cwd: &Path,
+        needles: &[&Path],
+        existing: Option<usize>,
+    ) -> Result<Vec<MatchEntry>> {
+        Ok(match_entries(self, cwd, needles, existing))
+    }
+
+    fn jump(&mut self, config: &Config, cwd: &Path, needles: &[&Path]) -> Result<PathBuf> {
+        let (path, changed) = jump_target(config, self, cwd, needles);
+        if changed {
+            if let Err(e) = self.save(config) {
+                debug!("failed to forget the missing entries: {}", e);
+            }
+        }
+        Ok(path)
+    }
+
+    fn flush(&mut self, config: &Config) -> Result<()> {
+        self.save(config)
+    }
+}
+
+/// Records a visit of the entry and returns its new weight.
+pub fn add_entry(config: &Config, data: &mut Database, entry: &Path, weight: Option<f32>) -> f32 {
+    check_weighting(config, data);
+    let value = config
+        .weighting
+        .increase(*data.get(entry).unwrap_or(&0.0), weight);
+    data.touch(entry);
+    data.record_mount(entry);
+    data.set_weight(entry, value);
+    value
+}
+
+/// Decreases the weight of the entry and returns its new weight.
+pub fn decrease_entry(
+    config: &Config,
+    data: &mut Database,
+    entry: &Path,
+    weight: Option<f32>,
+) -> f32 {
+    check_weighting(config, data);
+    let value = config
+        .weighting
+        .decrease(*data.get(entry).unwrap_or(&0.0), weight);
+    data.set_weight(entry, value);
+    value
+}
+
+/// Returns the matches of the needles with their last visits, see
+/// `find_matches`.
+pub fn match_entries(
+    data: &Database,
+    cwd: &Path,
+    needles: &[&Path],
+    existing: Option<usize>,
+) -> Vec<MatchEntry> {
+    find_matches(data, cwd, needles, existing)
+        .into_iter()
+        .map(|(path, weight)| (path.to_path_buf(), weight, data.visited(path)))
+        .collect()
+}
+
+/// Returns the first `count` of the matches returned by `match_entries` which
+/// exist, and the ones found missing before them, like `find_existing_matches`
+/// but without holding the database, eg. by the daemon.
+pub fn existing_entries(ranked: Vec<MatchEntry>, count: usize) -> (Vec<MatchEntry>, Vec<PathBuf>) {
+    if ranked.first().is_some_and(|x| x.0 == Path::new(".")) {
+        return (ranked, vec![]);
+    }
+    let (found, missing) = first_existing(ranked.iter().map(|x| x.0.as_path()), count);
+    let missing = missing.into_iter().map(Path::to_path_buf).collect();
+    let mut ret: Vec<_> = found
+        .into_iter()
+        .filter_map(|path| ranked.iter().find(|x| x.0 == path).cloned())
+        .collect();
+    if ret.is_empty() {
+        ret.push((PathBuf::from("."), 0.0, None));
+    }
+    (ret, missing)
+}
+
+/// Returns the best match which exists, and if the entries found missing on
+/// the way have changed the database, see `forget_missing`.
+pub fn jump_target(
+    config: &Config,
+    data: &mut Database,
+    cwd: &Path,
+    needles: &[&Path],
+) -> (PathBuf, bool) {
+    let (results, missing) = find_existing_matches(data, cwd, needles, 1);
+    let path = results.first().unwrap().0.to_path_buf(); // never fail
+    let missing: Vec<_> = missing.into_iter().map(Path::to_path_buf).collect();
+    assert!(!path.as_os_str().is_empty());
+    let changed = forget_missing(config, data, &path, &missing);
+    (path, changed)
+}
 
 /// Add a new path or increment an existing one.
-/// path.canonicalize() is not used because it's preferable to use symlinks
-/// with resulting duplicate entries in the database than a single canonical path.
+/// path.canonicalize() is not used unless in canonical mode, because it's
+/// preferable to use symlinks with resulting duplicate entries in the database
+/// than a single canonical path, see `handle_dedupe` to merge them.
 pub fn handle_add_path(
     config: &Config,
-    data: &mut Database,
+    data: &mut impl Store,
     path: &Path,
     weight: Option<f32>,
     dryrun: bool,
 ) -> Result<()> {
-    let entry = absolute_path(normalize_path(path));
+    let entry = path_entry(config, &CWD, path);
     // TODO: what is it used for?
     if entry == Path::new(shellexpand::tilde("~").as_ref()) {
-        print_item((entry.to_string_lossy(), 0.0));
-        return Ok(());
+        return print_weight(config, &entry, 0.0);
     }
 
-    let value =
-        (data.get(&entry).unwrap_or(&0.0).powf(2.0) + weight.unwrap_or(10.0).powf(2.0)).sqrt();
-
-    print_item((entry.to_string_lossy(), value));
-
-    data.insert(entry, value);
+    let value = data.add(config, &entry, weight)?;
+    print_weight(config, &entry, value)?;
     if !dryrun {
-        data.save(&config)?;
+        data.flush(config)?;
     }
 
     Ok(())
@@ -44,50 +276,92 @@ pub fn handle_add_path(
 /// Decrease or zero out a path.
 pub fn handle_decrease_path(
     config: &Config,
-    data: &mut Database,
+    data: &mut impl Store,
     path: &Path,
     weight: Option<f32>,
     dryrun: bool,
 ) -> Result<()> {
-    let entry = normalize_path(path);
-    let value = (data.get(&entry).unwrap_or(&0.0) - weight.unwrap_or(15.0)).max(0.0);
-
-    print_item((entry.to_string_lossy(), value));
-
-    data.insert(entry, value);
+    let entry = path_entry(config, &CWD, path);
+    let value = data.decrease(config, &entry, weight)?;
+    print_weight(config, &entry, value)?;
     if !dryrun {
-        data.save(&config)?;
+        data.flush(config)?;
     }
 
     Ok(())
 }
 
 /// print the statistics from the database
-pub fn handle_print_stats(data: &Database, data_path: &Path) {
-    info!("Weight\t\tPath");
-    info!("{}", "-".repeat(80));
-    let mut count_vec: Vec<_> = data.iter().collect();
-    count_vec.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap_or(Ordering::Equal));
-    for (path, weight) in count_vec {
-        print_item((path.display(), *weight));
-    }
-
-    let sum: f32 = data.values().sum();
-    info!("{}", "_".repeat(80));
-    info!("{:.2}\t\ttotal weight", sum);
-    info!(
-        "{:width$}\t\ttotal entries",
-        data.len(),
-        width = (sum.log10().floor() as usize) + 4
-    );
+///
+/// With `print0` the entries are written to stdout as NUL-terminated
+/// `weight<TAB>path` records instead, keeping the paths byte for byte, and the
+/// tab-separated lines are terminated with a NUL too.
+pub fn handle_print_stats(
+    data: &Database,
+    data_path: &Path,
+    options: &StatsOptions,
+    format: StatsFormat,
+    print0: bool,
+) -> Result<()> {
+    if print0 && format == StatsFormat::Text {
+        let mut count_vec: Vec<_> = data.iter().collect();
+        count_vec.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap_or(Ordering::Equal));
+        for (path, weight) in count_vec {
+            print_bytes(
+                &[
+                    format!("{:.2}\t", weight).as_bytes(),
+                    path.as_os_str().as_encoded_bytes(),
+                ],
+                true,
+            )?;
+        }
+        return Ok(());
+    }
 
-    info!(
-        "{:.2}\t\tcurrent directory weight",
-        data.get(&normalize_path(&CWD)).unwrap_or(&0.0)
-    );
+    match format {
+        StatsFormat::Text => {
+            let report = stats_report(data, data_path, options);
+            // only fit the paths in the width of a terminal, never in a pipe
+            let width = if std::io::stdout().is_terminal() {
+                terminal_size::terminal_size().map(|(width, _)| width.0 as usize)
+            } else {
+                None
+            };
+            print!("{}", format_text(&report, width));
+        }
+        StatsFormat::Json | StatsFormat::Tsv => {
+            let options = StatsOptions {
+                abbreviate: false,
+                ..options.clone()
+            };
+            let report = stats_report(data, data_path, &options);
+            if format == StatsFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&format_json(&report)?)?);
+            } else {
+                for item in &report.items {
+                    print_tsv(&tsv_fields(item), &item.path, print0)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-    info!("");
-    info!("database file:\t{}", data_path.to_str().unwrap()); // never fail
+/// Returns the entries offered by the completion menu of the needles, whether
+/// they exist or not.
+fn menu_entries(
+    config: &Config,
+    data: &mut impl Store,
+    needles: &[&Path],
+) -> Result<Vec<MatchEntry>> {
+    let results = data.matches(&CWD, needles, None)?;
+    // a path can be matched by more than one matcher
+    let mut seen = HashSet::new();
+    Ok(results
+        .into_iter()
+        .filter(|x| seen.insert(x.0.clone()))
+        .take(config.completion_count)
+        .collect())
 }
 
 /// Find matched results
@@ -96,66 +370,529 @@ pub fn handle_print_stats(data: &Database, data_path: &Path) {
 /// ```text
 ///        [needle]__[index]__[path]
 /// ```
-fn find_results(needles: &[&Path], data: &Database, complete: bool) -> Result<()> {
+fn find_results(
+    config: &Config,
+    needles: &[&Path],
+    data: &mut impl Store,
+    complete: Option<CompleteFormat>,
+    print0: bool,
+) -> Result<()> {
+    let mut args: Vec<_> = needles
+        .iter()
+        .map(|x| x.as_os_str().as_encoded_bytes())
+        .collect();
+    let first_needle = args.first().copied().unwrap_or_default();
     // TODO: invalidate instead of normalize?
-    let needles: Vec<_> = needles.iter().map(|x| normalize_path(x)).collect();
-    let needles: Vec<_> = needles.iter().map(|x| x.as_path()).collect();
-    let first_needle = needles.get(0).unwrap_or(&Path::new("")).to_string_lossy();
-    let mut tabs = first_needle.split(TAB_SEPARATOR);
-    let tab_needle = tabs.next();
-    let tab_index = tabs.next();
-    let tab_path = tabs.next();
-
-    if let Some(path) = tab_path {
-        println!("{}", path);
-    } else if let Some(_index) = tab_index {
-        let index = _index.parse().unwrap_or(0);
-        println!(
-            "{}",
-            find_matches(data, &[Path::new(tab_needle.unwrap())], false) // never fail
-                .get(index)
-                .unwrap() // never fail
-                .0
-                .to_string_lossy()
-        );
-    } else if let Some(_needle) = tab_needle {
-        // found partial tab completion entry
-        if complete {
-            print_tab_menu(
-                _needle,
-                find_matches(data, &needles, false)
-                    .iter()
-                    .take(TAB_ENTRIES_COUNT),
-                TAB_SEPARATOR,
-            );
-        } else {
-            let results = find_matches(data, &needles, true);
-            let path = &results.get(0).unwrap().0; // never fail
-            assert!(!path.as_os_str().is_empty());
-            println!("{}", path.to_string_lossy());
+    let mut needles: Vec<_> = needles.iter().map(|x| normalize_path(x)).collect();
+
+    match parse_tab_entry(first_needle) {
+        TabEntry::Path { path, .. } => {
+            print_target(config, &expand_home(Path::new(&os_str(path))), print0)?;
+        }
+        TabEntry::Index { needle, index } => {
+            // the menu was offered for the same arguments but the index
+            args[0] = needle;
+            if let Some(path) = CompletionCache::load(config).get(&args, index) {
+                print_target(config, &path, print0)?;
+            } else {
+                // the menu is gone, match it again as it was offered, and
+                // never jump to another entry than the one selected
+                needles[0] = normalize_path(Path::new(&os_str(needle)));
+                let needles: Vec<_> = needles.iter().map(|x| x.as_path()).collect();
+                let entries = menu_entries(config, data, &needles)?;
+                let path = index
+                    .checked_sub(1)
+                    .and_then(|x| entries.get(x))
+                    .map_or(Path::new("."), |x| x.0.as_path());
+                print_target(config, path, print0)?;
+            }
+        }
+        TabEntry::Needle(needle) => {
+            let needles: Vec<_> = needles.iter().map(|x| x.as_path()).collect();
+            // found partial tab completion entry
+            if let Some(format) = complete {
+                let entries = menu_entries(config, data, &needles)?;
+                print_menu(config, needle, &entries, format, print0)?;
+
+                let mut cache = CompletionCache::load(config);
+                cache.insert(&args, entries.iter().map(|x| x.0.as_path()));
+                if let Err(e) = cache.save(config) {
+                    debug!("failed to save the completion cache: {}", e);
+                }
+            } else {
+                let path = data.jump(config, &CWD, &needles)?;
+                print_target(config, &path, print0)?;
+            }
         }
-    } else {
-        bail!("unexpected result from the &str split");
     }
     Ok(())
 }
 
+/// Marks the entries found missing by a jump, and forgets the ones which have
+/// been missing for longer than `forget_days`. Returns if anything changed.
+///
+/// Only the entries which are removed for sure are marked and forgotten, see
+/// `Mounts::is_removed`, not eg. the ones under the mount points which are
+/// absent at the moment, which are expected to come back, or the ones which
+/// can't be accessed.
+pub fn forget_missing(
+    config: &Config,
+    data: &mut Database,
+    found: &Path,
+    missing: &[PathBuf],
+) -> bool {
+    if config.forget_days == 0 {
+        return false;
+    }
+    let now = unix_time();
+    let mut changed = data.clear_missing(found);
+    let mounts = Mounts::load(data.mount_points());
+    for path in missing {
+        if mounts.is_removed(path) {
+            changed |= data.mark_missing(path, now);
+        }
+    }
+
+    let expired = data.missing_since(now.saturating_sub(config.forget_days * 24 * 60 * 60));
+    if !expired.is_empty() {
+        // check them again, they may have come back since
+        let (found, gone) = first_existing(expired.iter().map(|x| x.as_path()), usize::MAX);
+        let found: Vec<_> = found.into_iter().map(Path::to_path_buf).collect();
+        let gone: Vec<_> = gone.into_iter().map(Path::to_path_buf).collect();
+        for path in &found {
+            data.clear_missing(path);
+        }
+        for path in &gone {
+            if mounts.is_removed(path) {
+                debug!(
+                    "forgot {} missing for over {} days",
+                    path.display(),
+                    config.forget_days
+                );
+                data.remove(path);
+            } else {
+                data.clear_missing(path);
+            }
+        }
+        changed = true;
+    }
+    changed
+}
+
 /// Provide tab completion hints
-pub fn handle_tab_completion(needles: &[&Path], data: &Database) -> Result<()> {
-    find_results(needles, data, true)
+pub fn handle_tab_completion(
+    config: &Config,
+    needles: &[&Path],
+    data: &mut impl Store,
+    format: CompleteFormat,
+    print0: bool,
+) -> Result<()> {
+    find_results(config, needles, data, Some(format), print0)
 }
 
 /// Provide the result path best matched
-pub fn handle_jump(needles: &[&Path], data: &Database) -> Result<()> {
-    find_results(needles, data, false)
+pub fn handle_jump(
+    config: &Config,
+    needles: &[&Path],
+    data: &mut impl Store,
+    print0: bool,
+) -> Result<()> {
+    find_results(config, needles, data, None, print0)
+}
+
+/// Remove the entries of the directories which are gone
+///
+/// The entries under the mount points which are absent at the moment, or
+/// which can't be checked, are kept unless `unmounted` is set, so that
+/// unplugging a disk doesn't wipe out its history.
+pub fn handle_purge(
+    config: &Config,
+    data: &mut Database,
+    dryrun: bool,
+    interactive: bool,
+    unmounted: bool,
+) -> Result<()> {
+    let mounts = Mounts::load(data.mount_points());
+    let mut gone = vec![];
+    let mut kept = 0;
+    let mut results = vec![];
+    let mut paths: Vec<_> = data.keys().map(|x| x.as_path()).collect();
+    paths.sort();
+    for (path, state) in mounts.check_all(&paths) {
+        let action = if state.is_gone() || (unmounted && matches!(state, PathState::Unmounted(_))) {
+            gone.push(path.to_path_buf());
+            "remove"
+        } else if state != PathState::Directory {
+            kept += 1;
+            "keep"
+        } else {
+            continue;
+        };
+        if config.output == OutputFormat::Text {
+            info!("{}\t{}\t({})", action, path.display(), state);
+        }
+        results.push((action, state, path.to_path_buf()));
+    }
+
+    let confirmed = || -> Result<bool> {
+        eprint!("Remove {} entries? [y/N] ", gone.len());
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+    };
+    let removed = if gone.is_empty() {
+        info!("Nothing to purge.");
+        0
+    } else if interactive && !dryrun && !confirmed()? {
+        info!("Nothing is purged.");
+        0
+    } else {
+        for path in &gone {
+            data.remove(path);
+        }
+        if !dryrun {
+            data.save(config)?;
+        }
+        info!(
+            "Purged {} entries, kept {} which can't be checked.",
+            gone.len(),
+            kept
+        );
+        gone.len()
+    };
+
+    match config.output {
+        OutputFormat::Text => {}
+        OutputFormat::Json => print_json(&json!({
+            "entries": results
+                .iter()
+                .map(|(action, state, path)| {
+                    let entry = json!({
+                        "action": action,
+                        "state": state.to_string(),
+                    });
+                    with_path(entry, "path", path)
+                })
+                .collect::<Vec<_>>(),
+            "removed": removed,
+            "dryrun": dryrun,
+        }))?,
+        OutputFormat::Tsv => {
+            for (action, state, path) in &results {
+                print_tsv(&[action.to_string(), state.to_string()], path, false)?;
+            }
+        }
+    }
+    Ok(())
 }
 
-pub fn handle_purge(config: &Config, data: &mut Database, dryrun: bool) -> Result<()> {
-    let old_entries = data.len();
-    data.retain(|key, _| key.exists());
+/// Recompute the weights by the configured weighting
+pub fn handle_reweight(config: &Config, data: &mut Database, dryrun: bool) -> Result<()> {
+    let from = data.weighting();
+    if from == config.weighting {
+        info!("The database is already weighted by {}.", from);
+        return Ok(());
+    }
+
+    data.reweight(config.weighting);
     if !dryrun {
-        data.save(&config)?;
+        data.save(config)?;
+    }
+    info!(
+        "Reweighted {} entries from {} to {}.",
+        data.len(),
+        from,
+        config.weighting
+    );
+    Ok(())
+}
+
+/// Edit the weights of the paths, or of the entries matched by the patterns
+///
+/// The weights are printed before and after the edit, which is only saved
+/// unless it's a dry run.
+pub fn handle_edit_weight(
+    config: &Config,
+    data: &mut Database,
+    op: WeightEdit,
+    value: f32,
+    targets: &[PathBuf],
+    pattern: bool,
+    dryrun: bool,
+) -> Result<()> {
+    let mut edits: Vec<(PathBuf, Option<f32>)> = vec![];
+    if pattern {
+        let patterns: Vec<_> = targets.iter().map(|x| expand_home(x)).collect();
+        let patterns: Vec<_> = patterns.iter().map(|x| x.as_path()).collect();
+        let mut matches = match_glob(&patterns, data);
+        if matches.is_empty() {
+            return Err(anyhow!("no entries match the patterns"));
+        }
+        matches.sort_by(|a, b| a.0.cmp(b.0));
+        edits.extend(
+            matches
+                .into_iter()
+                .map(|(path, weight)| (path.to_path_buf(), Some(weight))),
+        );
+    } else {
+        for target in targets {
+            let entry = path_entry(config, &CWD, &expand_home(target));
+            let old = data.get(&entry).copied();
+            if old.is_none() && matches!(op, WeightEdit::Sub | WeightEdit::Scale) {
+                warn!("{} is not in the database, skipped", entry.display());
+                continue;
+            }
+            edits.push((entry, old));
+        }
+    }
+
+    for (entry, old) in edits {
+        let new = op.apply(old.unwrap_or(0.0), value);
+        info!(
+            "{:.2} -> {:.2}\t{}",
+            old.unwrap_or(0.0),
+            new,
+            entry.display()
+        );
+        data.insert(entry, new);
+    }
+    if !dryrun {
+        data.save(config)?;
+    }
+    Ok(())
+}
+
+/// Edit the entries in `$VISUAL` or `$EDITOR`
+///
+/// The edited entries are validated as a whole, so that nothing is changed
+/// unless all of them are valid.
+pub fn handle_edit(config: &Config, data: &mut Database, dryrun: bool) -> Result<()> {
+    let (entries, text) = dump(data);
+    let mut file = tempfile::Builder::new()
+        .prefix("fastjump-")
+        .suffix(".tsv")
+        .tempfile()?;
+    file.write_all(&text)?;
+    file.flush()?;
+
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .find(|x| !x.is_empty())
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+    // the editor may come with its own arguments, eg. `code --wait`
+    let mut args = editor.split_whitespace();
+    let program = args.next().ok_or_else(|| anyhow!("no editor is set"))?;
+    let status = std::process::Command::new(program)
+        .args(args)
+        .arg(file.path())
+        .status()
+        .map_err(|e| anyhow!("failed to run the editor {}: {}", program, e))?;
+    if !status.success() {
+        bail!(
+            "the editor {} exited with {}, nothing is changed",
+            program,
+            status
+        );
+    }
+
+    let edited = read(file.path())?;
+    let edited = parse(&edited)
+        .map_err(|errors| anyhow!("{}\nthe database is left unchanged", errors.join("\n")))?;
+    let changes = diff(&entries, &edited);
+    if changes.is_empty() {
+        info!("Nothing is changed.");
+        return Ok(());
+    }
+
+    check_weighting(config, data);
+    for change in &changes {
+        match change {
+            Change::Remove(path) => info!("removed\t{}", path.display()),
+            Change::Update(path, weight) => info!("{:.2}\t{}", weight, path.display()),
+            Change::Rename { from, to, .. } => {
+                info!("renamed\t{} -> {}", from.display(), to.display())
+            }
+            Change::Add(path, weight) => info!("added\t{:.2}\t{}", weight, path.display()),
+        }
+    }
+    apply(data, &changes);
+    if !dryrun {
+        data.save(config)?;
+    }
+    info!("Applied {} changes.", changes.len());
+    Ok(())
+}
+
+/// Merge the entries of the same directories reached through symlinks
+///
+/// The path kept is the canonical one in canonical mode, or the heaviest one
+/// otherwise, unless `keep` says which.
+pub fn handle_dedupe(
+    config: &Config,
+    data: &mut Database,
+    keep: Option<DedupeKeep>,
+    dryrun: bool,
+) -> Result<()> {
+    let keep = keep.unwrap_or(if config.canonical {
+        DedupeKeep::Canonical
+    } else {
+        DedupeKeep::Heaviest
+    });
+    check_weighting(config, data);
+
+    let merges = find_duplicates(data, keep);
+    for (from, into) in &merges {
+        info!("merge\t{} -> {}", from.display(), into.display());
+        data.merge_entry(from, into);
+    }
+    if !dryrun && !merges.is_empty() {
+        data.save(config)?;
+    }
+    info!("Merged {} entries.", merges.len());
+    Ok(())
+}
+
+/// Move the entries under a directory which has been moved or renamed
+///
+/// The entries which end up with the same path are merged.
+pub fn handle_move(
+    config: &Config,
+    data: &mut Database,
+    from: &Path,
+    to: &Path,
+    dryrun: bool,
+) -> Result<()> {
+    let from = path_entry(config, &CWD, &expand_home(from));
+    let to = path_entry(config, &CWD, &expand_home(to));
+    if !to.is_dir() {
+        warn!("{} is not a directory", to.display());
+    }
+    check_weighting(config, data);
+
+    let moves = data.move_prefix(&from, &to);
+    if moves.is_empty() {
+        bail!("no entries under {}", from.display());
+    }
+    for (path, target) in &moves {
+        info!("{} -> {}", path.display(), target.display());
+    }
+    if !dryrun {
+        data.save(config)?;
+    }
+    info!("Moved {} entries.", moves.len());
+    Ok(())
+}
+
+/// Merge another database, or a text file of its entries, into the database
+pub fn handle_merge(
+    config: &Config,
+    data: &mut Database,
+    file: &Path,
+    strategy: MergeStrategy,
+    ratio: f32,
+    maps: &[PathMap],
+    dryrun: bool,
+) -> Result<()> {
+    if !(0.0..=1.0).contains(&ratio) {
+        bail!("the ratio must be between 0 and 1 rather than {}", ratio);
+    }
+    if data.is_empty() {
+        data.reweight(config.weighting);
+    }
+    check_weighting(config, data);
+    let bytes = read(file).map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+    let entries =
+        load_source(&bytes, data.weighting()).map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+
+    let changes = merge_changes(data, entries, maps, strategy, ratio);
+    let mut added = 0;
+    for change in &changes {
+        match change.old {
+            Some(old) => info!("~ {} -> {}\t{}", old, change.new, change.path.display()),
+            None => {
+                info!("+ {}\t{}", change.new, change.path.display());
+                added += 1;
+            }
+        }
+    }
+    for change in &changes {
+        data.insert(change.path.clone(), change.new);
+        data.set_visited(&change.path, change.visited);
+    }
+    if !dryrun && !changes.is_empty() {
+        data.save(config)?;
+    }
+    info!(
+        "Added {} entries and changed {} entries.",
+        added,
+        changes.len() - added
+    );
+    Ok(())
+}
+
+/// Print the shell integration code
+pub fn handle_init(shell: Shell, cmd: &str, hook: Option<Hook>) -> Result<()> {
+    print!("{}", init_script(shell, cmd, hook)?);
+    Ok(())
+}
+
+/// Import the directories changed to in the history of a shell
+///
+/// Every `cd` which can be resolved to an existing directory is added as if
+/// it had been visited, at the time of the command if the history records it.
+pub fn handle_import(
+    config: &Config,
+    data: &mut Database,
+    shell: HistoryShell,
+    file: Option<&Path>,
+    dryrun: bool,
+) -> Result<()> {
+    let file = match file {
+        Some(file) => file.to_path_buf(),
+        None => shell
+            .default_file()
+            .ok_or_else(|| anyhow!("history file of {:?} not found", shell))?,
+    };
+    let history = read(&file).map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+
+    let home = PathBuf::from(shellexpand::tilde("~").as_ref());
+    let mut tracker = CdTracker::new();
+    let mut count = 0;
+    for entry in parse_history(shell, &history) {
+        for target in cd_targets(&entry.command) {
+            if let Some(dir) = tracker.cd(&target) {
+                let path = path_entry(config, &CWD, &dir);
+                // as `handle_add_path`, the home directory is not recorded
+                if path == home {
+                    continue;
+                }
+                // the import is not a visit, keep the time of the last real one
+                let visited = data.visited(&path).max(entry.time);
+                data.add(config, &path, None)?;
+                data.set_visited(&path, visited);
+                count += 1;
+            }
+        }
+    }
+
+    if !dryrun {
+        data.save(config)?;
+    }
+    match config.output {
+        OutputFormat::Text => info!(
+            "Imported {} directory changes from {}.",
+            count,
+            file.display()
+        ),
+        OutputFormat::Json => print_json(&with_path(
+            json!({ "imported": count, "dryrun": dryrun }),
+            "file",
+            &file,
+        ))?,
+        OutputFormat::Tsv => print_tsv(&[count.to_string()], &file, false)?,
     }
-    info!("Purged {} entries.", old_entries - data.len());
     Ok(())
 }
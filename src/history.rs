@@ -0,0 +1,471 @@
+use crate::common::utils::{clean_path, home_dir, path_from_bytes};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+pub const HISTORY_SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// The shells whose history files can be imported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistoryShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for HistoryShell {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(HistoryShell::Bash),
+            "zsh" => Ok(HistoryShell::Zsh),
+            "fish" => Ok(HistoryShell::Fish),
+            _ => Err(format!("unsupported history of shell {}", s)),
+        }
+    }
+}
+
+impl HistoryShell {
+    /// Returns the default history file of the shell, or `$HISTFILE` for bash
+    /// and zsh if it's exported.
+    pub fn default_file(self) -> Option<PathBuf> {
+        let home = home_dir()?;
+        match self {
+            HistoryShell::Bash | HistoryShell::Zsh => {
+                if let Some(file) = std::env::var_os("HISTFILE").filter(|x| !x.is_empty()) {
+                    return Some(PathBuf::from(file));
+                }
+                Some(home.join(match self {
+                    HistoryShell::Bash => ".bash_history",
+                    _ => ".zsh_history",
+                }))
+            }
+            HistoryShell::Fish => {
+                let data_home = std::env::var_os("XDG_DATA_HOME")
+                    .filter(|x| !x.is_empty())
+                    .map_or_else(|| home.join(".local").join("share"), PathBuf::from);
+                Some(data_home.join("fish").join("fish_history"))
+            }
+        }
+    }
+}
+
+/// A command read from a history file.
+#[derive(Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub command: Vec<u8>,
+    /// When the command was run, in seconds since the epoch, if recorded
+    pub time: Option<u64>,
+}
+
+fn parse_time(bytes: &[u8]) -> Option<u64> {
+    std::str::from_utf8(bytes).ok()?.trim().parse().ok()
+}
+
+/// Parses the history of bash, where a command can be preceded by a
+/// `#<timestamp>` line if `HISTTIMEFORMAT` is set.
+fn parse_bash(bytes: &[u8]) -> Vec<HistoryEntry> {
+    let mut ret = Vec::new();
+    let mut time = None;
+    for line in bytes.split(|x| *x == b'\n') {
+        if let Some(t) = line.strip_prefix(b"#").and_then(parse_time) {
+            time = Some(t);
+        } else if !line.is_empty() {
+            ret.push(HistoryEntry {
+                command: line.to_vec(),
+                time: time.take(),
+            });
+        }
+    }
+    ret
+}
+
+/// Reverts the metafication of zsh, which stores the bytes that are special
+/// to it as a 0x83 byte followed by the byte XOR 0x20.
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(x) = iter.next() {
+        match (*x, iter.clone().next()) {
+            (0x83, Some(next)) => {
+                ret.push(next ^ 0x20);
+                iter.next();
+            }
+            (x, _) => ret.push(x),
+        }
+    }
+    ret
+}
+
+/// Parses the history of zsh, either plain or in the extended format
+/// `: <start>:<elapsed>;<command>`, where the lines of a multi-line command
+/// end with a backslash.
+fn parse_zsh(bytes: &[u8]) -> Vec<HistoryEntry> {
+    let bytes = unmetafy(bytes);
+    let mut ret = Vec::new();
+    let mut lines = bytes.split(|x| *x == b'\n');
+
+    while let Some(line) = lines.next() {
+        let mut command = line.to_vec();
+        while command.last() == Some(&b'\\') {
+            command.pop();
+            command.push(b'\n');
+            match lines.next() {
+                Some(next) => command.extend_from_slice(next),
+                None => break,
+            }
+        }
+
+        let mut time = None;
+        if let Some(rest) = command.strip_prefix(b": ") {
+            if let Some(semicolon) = rest.iter().position(|x| *x == b';') {
+                let start = rest[..semicolon].split(|x| *x == b':').next();
+                time = start.and_then(parse_time);
+                if time.is_some() {
+                    command = rest[semicolon + 1..].to_vec();
+                }
+            }
+        }
+        if !command.is_empty() {
+            ret.push(HistoryEntry { command, time });
+        }
+    }
+    ret
+}
+
+/// Reverts the escaping of the commands in the history of fish.
+fn unescape_fish(bytes: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(x) = iter.next() {
+        match (*x, iter.clone().next()) {
+            (b'\\', Some(b'n')) => {
+                ret.push(b'\n');
+                iter.next();
+            }
+            (b'\\', Some(b'\\')) => {
+                ret.push(b'\\');
+                iter.next();
+            }
+            (x, _) => ret.push(x),
+        }
+    }
+    ret
+}
+
+/// Parses the YAML-like history of fish.
+fn parse_fish(bytes: &[u8]) -> Vec<HistoryEntry> {
+    let mut ret: Vec<HistoryEntry> = Vec::new();
+    for line in bytes.split(|x| *x == b'\n') {
+        if let Some(command) = line.strip_prefix(b"- cmd: ") {
+            ret.push(HistoryEntry {
+                command: unescape_fish(command),
+                time: None,
+            });
+        } else if let Some(time) = line.strip_prefix(b"  when: ") {
+            if let Some(entry) = ret.last_mut() {
+                entry.time = parse_time(time);
+            }
+        }
+    }
+    ret
+}
+
+/// Parses the history file of a shell into the commands.
+pub fn parse_history(shell: HistoryShell, bytes: &[u8]) -> Vec<HistoryEntry> {
+    match shell {
+        HistoryShell::Bash => parse_bash(bytes),
+        HistoryShell::Zsh => parse_zsh(bytes),
+        HistoryShell::Fish => parse_fish(bytes),
+    }
+}
+
+/// Where a `cd` command changes the directory to.
+#[derive(Debug, PartialEq)]
+pub enum CdTarget {
+    /// `cd` without arguments
+    Home,
+    /// `cd -`
+    Previous,
+    /// A path as written, `~` is not expanded yet
+    Path(Vec<u8>),
+    /// The path can't be known without running the shell, eg. `cd $dir`
+    Unknown,
+}
+
+/// A word of a command line, with the quotes removed.
+#[derive(Debug, Default)]
+struct Word {
+    text: Vec<u8>,
+    /// Whether there is an unquoted expansion, eg. `$x` or `*`
+    expanded: bool,
+}
+
+/// Splits a command line into simple commands, and each of them into words.
+///
+/// It follows the quoting rules of the POSIX shells, which is good enough for
+/// the `cd` commands of the other shells too.
+fn split_commands(command: &[u8]) -> Vec<Vec<Word>> {
+    let mut commands = vec![Vec::new()];
+    let mut word: Option<Word> = None;
+    let mut iter = command.iter().copied().peekable();
+
+    while let Some(x) = iter.next() {
+        match x {
+            b' ' | b'\t' => {
+                if let Some(word) = word.take() {
+                    commands.last_mut().unwrap().push(word);
+                }
+            }
+            b';' | b'&' | b'|' | b'\n' | b'(' | b')' => {
+                if let Some(word) = word.take() {
+                    commands.last_mut().unwrap().push(word);
+                }
+                commands.push(Vec::new());
+            }
+            b'\'' => {
+                let word = word.get_or_insert_with(Default::default);
+                for x in iter.by_ref() {
+                    if x == b'\'' {
+                        break;
+                    }
+                    word.text.push(x);
+                }
+            }
+            b'"' => {
+                let word = word.get_or_insert_with(Default::default);
+                while let Some(x) = iter.next() {
+                    match x {
+                        b'"' => break,
+                        b'\\' => {
+                            if let Some(next) = iter.next_if(|x| b"\"\\$`".contains(x)) {
+                                word.text.push(next);
+                            } else {
+                                word.text.push(x);
+                            }
+                        }
+                        b'$' | b'`' => {
+                            word.expanded = true;
+                            word.text.push(x);
+                        }
+                        _ => word.text.push(x),
+                    }
+                }
+            }
+            b'\\' => {
+                let word = word.get_or_insert_with(Default::default);
+                if let Some(next) = iter.next() {
+                    word.text.push(next);
+                }
+            }
+            b'$' | b'`' | b'*' | b'?' | b'[' | b'{' => {
+                let word = word.get_or_insert_with(Default::default);
+                word.expanded = true;
+                word.text.push(x);
+            }
+            _ => word.get_or_insert_with(Default::default).text.push(x),
+        }
+    }
+    if let Some(word) = word.take() {
+        commands.last_mut().unwrap().push(word);
+    }
+    commands.retain(|x| !x.is_empty());
+    commands
+}
+
+/// The words which can precede a command.
+const COMMAND_PREFIXES: &[&[u8]] = &[
+    b"builtin", b"command", b"do", b"then", b"else", b"{", b"!", b"time",
+];
+
+/// Returns where the `cd` and `pushd` commands of a command line change the
+/// directory to, in order.
+pub fn cd_targets(command: &[u8]) -> Vec<CdTarget> {
+    let mut ret = Vec::new();
+    for words in split_commands(command) {
+        let mut words = words.into_iter().peekable();
+        // eg. `builtin cd`, `if ...; then cd`
+        while words
+            .next_if(|x| COMMAND_PREFIXES.contains(&x.text.as_slice()))
+            .is_some()
+        {}
+        match words.next() {
+            Some(x) if x.text == b"cd" || x.text == b"pushd" => {}
+            _ => continue,
+        }
+        // skip the options, eg. cd -P
+        while words
+            .next_if(|x| x.text.len() > 1 && x.text[0] == b'-' && x.text != b"--")
+            .is_some()
+        {}
+        words.next_if(|x| x.text == b"--");
+
+        ret.push(match words.next() {
+            None => CdTarget::Home,
+            Some(x) if x.expanded => CdTarget::Unknown,
+            Some(x) if x.text == b"-" => CdTarget::Previous,
+            Some(x) => CdTarget::Path(x.text),
+        });
+    }
+    ret
+}
+
+/// Follows the working directory of a shell through the history, as the
+/// relative `cd` targets depend on where they were run.
+///
+/// The history doesn't tell where a session started, so a relative target is
+/// resolved against the home directory if the working directory is unknown.
+#[derive(Debug, Default)]
+pub struct CdTracker {
+    cwd: Option<PathBuf>,
+    previous: Option<PathBuf>,
+}
+
+impl CdTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn resolve(&self, target: &CdTarget) -> Option<PathBuf> {
+        let path = match target {
+            CdTarget::Home => return home_dir(),
+            CdTarget::Previous => return self.previous.clone(),
+            CdTarget::Unknown => return None,
+            CdTarget::Path(path) => path,
+        };
+        let path = match path.strip_prefix(b"~") {
+            Some(rest) if rest.is_empty() || rest[0] == b'/' => {
+                home_dir()?.join(path_from_bytes(rest.get(1..).unwrap_or(&[]).to_vec())?)
+            }
+            // ~user
+            Some(_) => return None,
+            None => path_from_bytes(path.clone())?,
+        };
+        if path.is_absolute() {
+            Some(path)
+        } else {
+            Some(self.cwd.clone().or_else(home_dir)?.join(path))
+        }
+    }
+
+    /// Changes the directory, returns the new one if it's known and exists.
+    pub fn cd(&mut self, target: &CdTarget) -> Option<PathBuf> {
+        let dir = self
+            .resolve(target)
+            .map(|x| clean_path(&x))
+            .filter(|x| x.is_dir());
+        self.previous = std::mem::replace(&mut self.cwd, dir.clone());
+        dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_history() {
+        let bash = b"cd /tmp\n#1600000000\ncd foo\n\nls\n";
+        assert_eq!(
+            parse_history(HistoryShell::Bash, bash),
+            vec![
+                HistoryEntry {
+                    command: b"cd /tmp".to_vec(),
+                    time: None
+                },
+                HistoryEntry {
+                    command: b"cd foo".to_vec(),
+                    time: Some(1600000000)
+                },
+                HistoryEntry {
+                    command: b"ls".to_vec(),
+                    time: None
+                },
+            ]
+        );
+
+        let zsh = b": 1600000000:0;cd /tmp\ncd b\x83\xa3r\n: 1600000001:2;for x in a; do\\\ncd $x\\\ndone\n";
+        assert_eq!(
+            parse_history(HistoryShell::Zsh, zsh),
+            vec![
+                HistoryEntry {
+                    command: b"cd /tmp".to_vec(),
+                    time: Some(1600000000)
+                },
+                HistoryEntry {
+                    command: b"cd b\x83r".to_vec(),
+                    time: None
+                },
+                HistoryEntry {
+                    command: b"for x in a; do\ncd $x\ndone".to_vec(),
+                    time: Some(1600000001)
+                },
+            ]
+        );
+
+        let fish =
+            b"- cmd: cd /tmp\n  when: 1600000000\n  paths:\n    - /tmp\n- cmd: echo a\\\\nb\\nc\n";
+        assert_eq!(
+            parse_history(HistoryShell::Fish, fish),
+            vec![
+                HistoryEntry {
+                    command: b"cd /tmp".to_vec(),
+                    time: Some(1600000000)
+                },
+                HistoryEntry {
+                    command: b"echo a\\nb\nc".to_vec(),
+                    time: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cd_targets() {
+        let path = |x: &[u8]| CdTarget::Path(x.to_vec());
+
+        assert_eq!(cd_targets(b"cd /tmp"), vec![path(b"/tmp")]);
+        assert_eq!(cd_targets(b"ls; cd"), vec![CdTarget::Home]);
+        assert_eq!(cd_targets(b"cd -P -- -"), vec![CdTarget::Previous]);
+        assert_eq!(cd_targets(b"cd -- -foo"), vec![path(b"-foo")]);
+        assert_eq!(
+            cd_targets(b"cd 'foo bar' && builtin cd \"b\\\"az\"/qux|pushd a\\ b"),
+            vec![path(b"foo bar"), path(b"b\"az/qux"), path(b"a b")]
+        );
+        assert_eq!(
+            cd_targets(b"cd $HOME/foo; cd \"$x\"; cd *.d"),
+            vec![CdTarget::Unknown, CdTarget::Unknown, CdTarget::Unknown]
+        );
+        assert_eq!(cd_targets(b"echo cd /tmp; cdx foo"), vec![]);
+        assert_eq!(
+            cd_targets(b"if true; then cd /tmp; fi"),
+            vec![path(b"/tmp")]
+        );
+    }
+
+    #[test]
+    fn test_cd_tracker() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_dir_all(root.join("foo").join("bar")).unwrap();
+
+        let path = |x: &Path| CdTarget::Path(x.as_os_str().as_encoded_bytes().to_vec());
+        let mut tracker = CdTracker::new();
+        assert_eq!(tracker.cd(&path(root)), Some(root.to_path_buf()));
+        assert_eq!(
+            tracker.cd(&path(Path::new("foo/bar"))),
+            Some(root.join("foo").join("bar"))
+        );
+        assert_eq!(tracker.cd(&path(Path::new(".."))), Some(root.join("foo")));
+        assert_eq!(
+            tracker.cd(&CdTarget::Previous),
+            Some(root.join("foo").join("bar"))
+        );
+        assert_eq!(tracker.cd(&path(Path::new("nothing"))), None);
+        // the working directory is lost
+        assert_eq!(tracker.cd(&path(Path::new("fastjump-bar"))), None);
+        assert_eq!(tracker.cd(&CdTarget::Unknown), None);
+    }
+}
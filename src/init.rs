@@ -0,0 +1,185 @@
+use anyhow::{bail, Result};
+use std::str::FromStr;
+
+pub const SHELLS: &[&str] = &[
+    "bash", "zsh", "fish", "tcsh", "nu", "elvish", "xonsh", "pwsh", "posix", "sh", "dash", "ksh",
+];
+pub const HOOKS: &[&str] = &["prompt", "pwd"];
+
+/// The shells `fastjump init` can integrate with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Tcsh,
+    Nu,
+    Elvish,
+    Xonsh,
+    Pwsh,
+    /// sh, dash, ash, ksh and other shells with POSIX features only
+    Posix,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "tcsh" => Ok(Shell::Tcsh),
+            "nu" => Ok(Shell::Nu),
+            "elvish" => Ok(Shell::Elvish),
+            "xonsh" => Ok(Shell::Xonsh),
+            "pwsh" => Ok(Shell::Pwsh),
+            "posix" | "sh" | "dash" | "ksh" => Ok(Shell::Posix),
+            _ => Err(format!("unsupported shell {}", s)),
+        }
+    }
+}
+
+impl Shell {
+    /// The integration code template, embedded at compile time.
+    fn template(self) -> &'static str {
+        match self {
+            Shell::Bash => include_str!("../scripts/init/fastjump.bash"),
+            Shell::Zsh => include_str!("../scripts/init/fastjump.zsh"),
+            Shell::Fish => include_str!("../scripts/init/fastjump.fish"),
+            Shell::Tcsh => include_str!("../scripts/init/fastjump.tcsh"),
+            Shell::Nu => include_str!("../scripts/init/fastjump.nu"),
+            Shell::Elvish => include_str!("../scripts/init/fastjump.elv"),
+            Shell::Xonsh => include_str!("../scripts/init/fastjump.xsh"),
+            Shell::Pwsh => include_str!("../scripts/init/fastjump.ps1"),
+            Shell::Posix => include_str!("../scripts/init/fastjump.posix"),
+        }
+    }
+
+    /// The hook style used unless specified, the same as the installed scripts.
+    fn default_hook(self) -> Hook {
+        match self {
+            Shell::Bash => Hook::Prompt,
+            _ => Hook::Pwd,
+        }
+    }
+}
+
+/// When the shell records the current directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hook {
+    /// On every prompt
+    Prompt,
+    /// On directory changes
+    Pwd,
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "prompt" => Ok(Hook::Prompt),
+            "pwd" => Ok(Hook::Pwd),
+            _ => Err(format!("unknown hook style {}", s)),
+        }
+    }
+}
+
+impl Hook {
+    fn name(self) -> &'static str {
+        match self {
+            Hook::Prompt => "prompt",
+            Hook::Pwd => "pwd",
+        }
+    }
+}
+
+/// Renders a template.
+///
+/// `{{name}}` is replaced with the value of the variable, and the lines
+/// between the `{{#name}}` and `{{/name}}` lines are kept only if the section
+/// is enabled.
+fn render(template: &str, vars: &[(&str, &str)], sections: &[&str]) -> String {
+    let mut ret = String::with_capacity(template.len());
+    let mut skipping: Option<&str> = None;
+
+    for line in template.lines() {
+        let marker = line.trim();
+        if let Some(name) = marker
+            .strip_prefix("{{#")
+            .and_then(|x| x.strip_suffix("}}"))
+        {
+            if skipping.is_none() && !sections.contains(&name) {
+                skipping = Some(name);
+            }
+            continue;
+        }
+        if let Some(name) = marker
+            .strip_prefix("{{/")
+            .and_then(|x| x.strip_suffix("}}"))
+        {
+            if skipping == Some(name) {
+                skipping = None;
+            }
+            continue;
+        }
+        if skipping.is_some() {
+            continue;
+        }
+
+        let mut line = line.to_string();
+        for (name, value) in vars {
+            line = line.replace(&format!("{{{{{}}}}}", name), value);
+        }
+        ret.push_str(&line);
+        ret.push('\n');
+    }
+    ret
+}
+
+/// Returns the integration code of the shell.
+///
+/// The jump command is named `cmd`, the other commands are named after it,
+/// eg. `jc`, `jo` and `jco` for `j`.
+pub fn init_script(shell: Shell, cmd: &str, hook: Option<Hook>) -> Result<String> {
+    if cmd.is_empty()
+        || !cmd
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        bail!("invalid command name {:?}", cmd);
+    }
+    let hook = hook.unwrap_or_else(|| shell.default_hook());
+
+    Ok(render(shell.template(), &[("cmd", cmd)], &[hook.name()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "a {{x}}\n{{#foo}}\nfoo\n{{/foo}}\n  {{#bar}}\nbar {{x}}\n  {{/bar}}\nb\n";
+        assert_eq!(render(template, &[("x", "1")], &["foo"]), "a 1\nfoo\nb\n");
+        assert_eq!(render(template, &[("x", "2")], &["bar"]), "a 2\nbar 2\nb\n");
+    }
+
+    #[test]
+    fn test_init_script() {
+        for shell in SHELLS {
+            let shell: Shell = shell.parse().unwrap();
+            for hook in HOOKS {
+                let script = init_script(shell, "k", Some(hook.parse().unwrap())).unwrap();
+                assert!(!script.contains("{{"));
+            }
+        }
+
+        let script = init_script(Shell::Zsh, "k", None).unwrap();
+        assert!(script.contains("compdef _k k"));
+        assert!(script.contains("chpwd_functions+=__fastjump_hook"));
+        assert!(!script.contains("precmd_functions"));
+        assert!(init_script(Shell::Bash, "j; rm", None).is_err());
+    }
+}
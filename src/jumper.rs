@@ -0,0 +1,220 @@
+use crate::common::config::Config;
+use crate::common::utils::{home_dir, normalize_path};
+use crate::database::Database;
+use crate::handlers::{add_entry, decrease_entry, jump_target, match_entries, path_entry};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// An entry matched by the needles.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Match {
+    pub path: PathBuf,
+    pub weight: f32,
+    /// When the entry was visited the last time, in seconds since the epoch
+    pub visited: Option<u64>,
+}
+
+/// The weight of an entry before and after it was changed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WeightChange {
+    pub path: PathBuf,
+    /// The weight before the change, if the entry existed
+    pub old: Option<f32>,
+    pub new: f32,
+}
+
+/// The database with the settings and the working directory to match and
+/// record the entries with, for embedding fastjump into other programs.
+///
+/// Unlike the command line, it never prints anything nor depends on the
+/// working directory of the process, and it only saves the database when
+/// asked to by `save`.
+pub struct Jumper {
+    config: Config,
+    cwd: PathBuf,
+    data: Database,
+    dirty: bool,
+}
+
+impl Jumper {
+    /// Loads the database of the config, with the relative paths resolved
+    /// against `cwd`.
+    pub fn open(config: Config, cwd: impl Into<PathBuf>) -> Result<Jumper> {
+        let data = Database::new(&config)?;
+        Ok(Jumper::with_database(config, cwd, data))
+    }
+
+    /// Wraps a database which is already loaded, or built in memory.
+    pub fn with_database(config: Config, cwd: impl Into<PathBuf>, mut data: Database) -> Jumper {
+        // usually queried many times, eg. by a plugin
+        data.keep_index();
+        Jumper {
+            config,
+            cwd: cwd.into(),
+            data,
+            dirty: false,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the directory which the relative paths are resolved against,
+    /// and which is left out of the matches.
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    pub fn set_cwd(&mut self, cwd: impl Into<PathBuf>) {
+        self.cwd = cwd.into();
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.data
+    }
+
+    /// Returns the database to change it directly, which marks it as changed.
+    pub fn database_mut(&mut self) -> &mut Database {
+        self.dirty = true;
+        &mut self.data
+    }
+
+    pub fn into_database(self) -> Database {
+        self.data
+    }
+
+    /// Checks if the database has changes which are not saved yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Records a visit of the directory, by the default weight of a visit
+    /// unless `weight` is given.
+    ///
+    /// The home directory is never recorded, as by the shell integration, in
+    /// which case `None` is returned.
+    pub fn add(&mut self, path: &Path, weight: Option<f32>) -> Option<WeightChange> {
+        let entry = path_entry(&self.config, &self.cwd, path);
+        if Some(entry.as_path()) == home_dir().as_deref() {
+            return None;
+        }
+        let old = self.data.get(&entry).copied();
+        let new = add_entry(&self.config, &mut self.data, &entry, weight);
+        self.dirty = true;
+        Some(WeightChange {
+            path: entry,
+            old,
+            new,
+        })
+    }
+
+    /// Decreases the weight of the directory, by the default weight of a
+    /// decrease unless `weight` is given.
+    pub fn decrease(&mut self, path: &Path, weight: Option<f32>) -> WeightChange {
+        let entry = path_entry(&self.config, &self.cwd, path);
+        let old = self.data.get(&entry).copied();
+        let new = decrease_entry(&self.config, &mut self.data, &entry, weight);
+        self.dirty = true;
+        WeightChange {
+            path: entry,
+            old,
+            new,
+        }
+    }
+
+    /// Returns all of the entries matched by the needles, the best first,
+    /// whether they exist or not.
+    pub fn matches(&self, needles: &[&Path]) -> Vec<Match> {
+        self.find(needles, None)
+    }
+
+    /// Returns the first `count` entries matched by the needles which exist.
+    pub fn existing_matches(&self, needles: &[&Path], count: usize) -> Vec<Match> {
+        self.find(needles, Some(count))
+    }
+
+    fn find(&self, needles: &[&Path], existing: Option<usize>) -> Vec<Match> {
+        let needles: Vec<_> = needles.iter().map(|x| normalize_path(x)).collect();
+        let needles: Vec<_> = needles.iter().map(|x| x.as_path()).collect();
+        // a path can be matched by more than one matcher
+        let mut seen = HashSet::new();
+        match_entries(&self.data, &self.cwd, &needles, existing)
+            .into_iter()
+            // the placeholder of no match
+            .filter(|x| x.0 != Path::new("."))
+            .filter(|x| seen.insert(x.0.clone()))
+            .map(|(path, weight, visited)| Match {
+                path,
+                weight,
+                visited,
+            })
+            .collect()
+    }
+
+    /// Returns the best match of the needles which exists, as a jump does.
+    ///
+    /// The entries found missing on the way are marked, and forgotten once
+    /// they have been missing for `forget_days`.
+    pub fn jump(&mut self, needles: &[&Path]) -> Option<PathBuf> {
+        let needles: Vec<_> = needles.iter().map(|x| normalize_path(x)).collect();
+        let needles: Vec<_> = needles.iter().map(|x| x.as_path()).collect();
+        let (path, changed) = jump_target(&self.config, &mut self.data, &self.cwd, &needles);
+        self.dirty |= changed;
+        Some(path).filter(|x| x != Path::new("."))
+    }
+
+    /// Saves the database if it has changed.
+    pub fn save(&mut self) -> Result<()> {
+        if self.dirty {
+            self.data.save(&self.config)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_jumper() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::new(None, Some(dir.path().join("data")));
+        let project = dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let mut jumper = Jumper::with_database(config, dir.path(), Database::from(HashMap::new()));
+
+        let change = jumper.add(Path::new("project"), None).unwrap();
+        assert_eq!(change.path, project);
+        assert_eq!(change.old, None);
+        jumper.add(Path::new("gone"), Some(100.0));
+        assert!(jumper.is_dirty());
+
+        let matches = jumper.matches(&[Path::new("o")]);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].path, dir.path().join("gone"));
+        assert_eq!(
+            jumper.existing_matches(&[Path::new("o")], 5)[0].path,
+            project
+        );
+        assert_eq!(jumper.jump(&[Path::new("o")]), Some(project.clone()));
+        assert_eq!(jumper.jump(&[Path::new("nothing")]), None);
+
+        // the working directory is left out
+        jumper.set_cwd(&project);
+        assert_eq!(jumper.matches(&[Path::new("project")]), []);
+
+        let change = jumper.decrease(&project, Some(1.0));
+        assert_eq!(change.new, change.old.unwrap() - 1.0);
+
+        jumper.save().unwrap();
+        assert!(!jumper.is_dirty());
+        let loaded = Database::new(jumper.config()).unwrap();
+        assert_eq!(loaded.len(), 2);
+    }
+}
@@ -1,3 +1,42 @@
+//! Jump to any directory fast and smart.
+//!
+//! Besides the command line, fastjump can be embedded into other programs,
+//! eg. the plugins of file managers and editors, by `Jumper`. It matches and
+//! records the directories with an explicit config and working directory,
+//! and returns the results as values rather than printing them.
+//!
+//! ```
+//! use fastjump::{Config, Jumper};
+//! use std::path::Path;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let dir = tempfile::tempdir()?;
+//! # std::fs::create_dir(dir.path().join("project"))?;
+//! # let (cwd, data_dir) = (dir.path(), Some(dir.path().join("data")));
+//! let mut jumper = Jumper::open(Config::new(None, data_dir), cwd)?;
+//! jumper.add(Path::new("project"), None);
+//! let target = jumper.jump(&[Path::new("proj")]);
+//! assert_eq!(target, Some(cwd.join("project")));
+//! jumper.save()?;
+//! # Ok(())
+//! # }
+//! ```
+
 pub mod common;
+pub mod completion;
+#[cfg(target_family = "unix")]
+pub mod daemon;
 pub mod database;
+pub mod dedupe;
+pub mod edit;
 pub mod handlers;
+pub mod history;
+pub mod init;
+pub mod jumper;
+pub mod merge;
+pub mod purge;
+pub mod stats;
+
+pub use common::config::Config;
+pub use database::Database;
+pub use jumper::{Jumper, Match, WeightChange};
@@ -1,11 +1,18 @@
 use anyhow::Result;
-use fastjump::common::opts::Opts;
+use fastjump::common::config::Config;
+use fastjump::common::opts::{Command, CommandOpts, Opts};
+use fastjump::common::output::OutputFormat;
 use fastjump::common::utils::{environment_check, setup_logger, CWD};
+#[cfg(target_family = "unix")]
+use fastjump::daemon;
 use fastjump::database::Database;
 use fastjump::handlers::{
-    handle_add_path, handle_decrease_path, handle_jump, handle_print_stats, handle_purge,
-    handle_tab_completion,
+    handle_add_path, handle_decrease_path, handle_dedupe, handle_edit, handle_edit_weight,
+    handle_import, handle_init, handle_jump, handle_merge, handle_move, handle_print_stats,
+    handle_purge, handle_reweight, handle_tab_completion, Store,
 };
+use fastjump::stats::{StatsFormat, StatsOptions};
+use log::debug;
 use structopt::StructOpt;
 
 // TODO: cleanup - remove unwrap
@@ -15,36 +22,186 @@ use structopt::StructOpt;
 // TODO: j <empty> go to the most recently dir
 // TODO: expand to abs path for add
 
+/// Returns the format of the statistics, unless `--output` overrides it.
+fn stats_format(config: &Config, format: StatsFormat) -> StatsFormat {
+    match config.output {
+        OutputFormat::Text => format,
+        OutputFormat::Json => StatsFormat::Json,
+        OutputFormat::Tsv => StatsFormat::Tsv,
+    }
+}
+
+/// Loads the database, after the daemon if any has saved its changes.
+fn load_database(config: &Config) -> Result<Database> {
+    #[cfg(target_family = "unix")]
+    daemon::flush(config);
+    Database::new(config)
+}
+
+fn run_command(opts: CommandOpts) -> Result<()> {
+    setup_logger(opts.verbose);
+    Config::check_env()?;
+    let mut config = Config::new(opts.profile, opts.data_dir);
+    if let Some(output) = opts.output {
+        config.output = output;
+    }
+
+    match opts.cmd {
+        // the shell integration is not sourced yet when it's being generated
+        Command::Init { shell, cmd, hook } => handle_init(shell, &cmd, hook),
+        Command::Import {
+            shell,
+            file,
+            dryrun,
+        } => {
+            let mut data = load_database(&config)?;
+            handle_import(&config, &mut data, shell, file.as_deref(), dryrun)
+        }
+        Command::Reweight { dryrun } => {
+            let mut data = load_database(&config)?;
+            handle_reweight(&config, &mut data, dryrun)
+        }
+        Command::Stats {
+            top,
+            pattern,
+            prefix,
+            sort,
+            reverse,
+            group,
+            no_abbrev,
+            format,
+        } => {
+            let data = load_database(&config)?;
+            let options = StatsOptions {
+                top,
+                pattern,
+                prefix,
+                sort,
+                reverse,
+                group,
+                abbreviate: !no_abbrev,
+            };
+            let format = stats_format(&config, format);
+            handle_print_stats(&data, &config.data_path, &options, format, false)
+        }
+        Command::Purge {
+            interactive,
+            unmounted,
+            dryrun,
+        } => {
+            let mut data = load_database(&config)?;
+            handle_purge(&config, &mut data, dryrun, interactive, unmounted)
+        }
+        Command::Dedupe { keep, dryrun } => {
+            let mut data = load_database(&config)?;
+            handle_dedupe(&config, &mut data, keep, dryrun)
+        }
+        Command::Move { from, to, dryrun } => {
+            let mut data = load_database(&config)?;
+            handle_move(&config, &mut data, &from, &to, dryrun)
+        }
+        Command::Merge {
+            file,
+            strategy,
+            ratio,
+            map,
+            dryrun,
+        } => {
+            let mut data = load_database(&config)?;
+            handle_merge(&config, &mut data, &file, strategy, ratio, &map, dryrun)
+        }
+        Command::Daemon { interval, stop } => {
+            #[cfg(target_family = "unix")]
+            if stop {
+                daemon::stop(&config)
+            } else {
+                daemon::serve(config, std::time::Duration::from_secs(interval.max(1)))
+            }
+            #[cfg(not(target_family = "unix"))]
+            {
+                let _ = (interval, stop);
+                anyhow::bail!("the daemon is only supported on Unix")
+            }
+        }
+        Command::Edit { dryrun } => {
+            let mut data = load_database(&config)?;
+            handle_edit(&config, &mut data, dryrun)
+        }
+        Command::Weight {
+            op,
+            value,
+            targets,
+            pattern,
+            dryrun,
+        } => {
+            let mut data = load_database(&config)?;
+            handle_edit_weight(&config, &mut data, op, value, &targets, pattern, dryrun)
+        }
+    }
+}
+
 fn main() -> Result<()> {
-    environment_check()?;
+    let args: Vec<_> = std::env::args_os().collect();
+    if CommandOpts::is_command(&args) {
+        return run_command(CommandOpts::from_iter(args));
+    }
 
     let opts = Opts::from_args();
-    setup_logger(&opts);
+    setup_logger(opts.verbose);
+    environment_check()?;
+    Config::check_env()?;
 
-    let config = Default::default();
-    let mut data = Database::new(&config)?;
+    let mut config = Config::new(opts.profile.clone(), opts.data_dir.clone());
+    if let Some(output) = opts.output {
+        config.output = output;
+    }
+    let store_only = opts.add.is_some()
+        || opts.complete
+        || opts.decrease.is_some()
+        || opts.increase.is_some()
+        || !(opts.purge || opts.stat);
+    if store_only {
+        // served by the daemon if it's running, see `fastjump daemon`
+        #[cfg(target_family = "unix")]
+        if !opts.dryrun {
+            if let Some(mut client) = daemon::Client::connect(&config) {
+                match run_store(&config, &opts, &mut client) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => debug!("falling back to the database file: {}", e),
+                }
+            }
+        }
+        return run_store(&config, &opts, &mut Database::new(&config)?);
+    }
 
-    if let Some(directory) = opts.add {
-        handle_add_path(&config, &mut data, &directory, None, opts.dryrun)?;
-    } else if opts.complete {
-        handle_tab_completion(
-            &opts.paths.iter().map(|x| x.as_path()).collect::<Vec<_>>(),
+    let mut data = load_database(&config)?;
+    if opts.purge {
+        handle_purge(&config, &mut data, opts.dryrun, false, false)?;
+    } else {
+        handle_print_stats(
             &data,
+            config.data_path.as_path(),
+            &Default::default(),
+            stats_format(&config, StatsFormat::Text),
+            opts.print0,
         )?;
+    }
+    Ok(())
+}
+
+/// Runs the options which record or match the entries, see `Store`.
+fn run_store(config: &Config, opts: &Opts, data: &mut impl Store) -> Result<()> {
+    let paths: Vec<_> = opts.paths.iter().map(|x| x.as_path()).collect();
+    if let Some(directory) = &opts.add {
+        handle_add_path(config, data, directory, None, opts.dryrun)
+    } else if opts.complete {
+        handle_tab_completion(config, &paths, data, opts.complete_format, opts.print0)
     } else if opts.decrease.is_some() {
-        handle_decrease_path(&config, &mut data, &CWD, None, opts.dryrun)?;
+        handle_decrease_path(config, data, &CWD, opts.decrease, opts.dryrun)
     } else if opts.increase.is_some() {
-        handle_add_path(&config, &mut data, &CWD, opts.increase, opts.dryrun)?;
-    } else if opts.purge {
-        handle_purge(&config, &mut data, opts.dryrun)?;
-    } else if opts.stat {
-        handle_print_stats(&data, config.data_path.as_path());
+        handle_add_path(config, data, &CWD, opts.increase, opts.dryrun)
     } else {
         // TODO: move to the top
-        handle_jump(
-            &opts.paths.iter().map(|x| x.as_path()).collect::<Vec<_>>(),
-            &data,
-        )?;
+        handle_jump(config, &paths, data, opts.print0)
     }
-    Ok(())
 }
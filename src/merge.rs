@@ -0,0 +1,266 @@
+use crate::common::utils::expand_home;
+use crate::common::weighting::Weighting;
+use crate::database::{Database, MAGIC};
+use crate::edit::parse;
+use anyhow::{anyhow, Result};
+use std::collections::hash_map::{Entry, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const MERGE_STRATEGIES: &[&str] = &["max", "sum", "average"];
+
+/// How the weights of an entry in both of the databases are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeStrategy {
+    /// The larger weight
+    Max,
+    /// The weight of all of the visits on both of the machines
+    Sum,
+    /// The average of the weights, see `merge_changes`
+    Average,
+}
+
+impl FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(MergeStrategy::Max),
+            "sum" => Ok(MergeStrategy::Sum),
+            "average" => Ok(MergeStrategy::Average),
+            _ => Err(format!("unknown merge strategy {}", s)),
+        }
+    }
+}
+
+/// Replaces a path prefix of the other database, eg. its home directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMap {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl FromStr for PathMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(PathMap {
+                from: PathBuf::from(from),
+                to: expand_home(Path::new(to)),
+            }),
+            _ => Err(format!("expected OLD=NEW rather than {}", s)),
+        }
+    }
+}
+
+/// Replaces the longest matching prefix of the path.
+pub fn remap(path: &Path, maps: &[PathMap]) -> PathBuf {
+    maps.iter()
+        .filter_map(|map| Some((map, path.strip_prefix(&map.from).ok()?)))
+        .max_by_key(|(map, _)| map.from.components().count())
+        .map(|(map, rest)| {
+            if rest.as_os_str().is_empty() {
+                map.to.clone()
+            } else {
+                map.to.join(rest)
+            }
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// An entry of the other database.
+pub type SourceEntry = (PathBuf, f32, Option<u64>);
+
+/// Reads the entries of another database, or of a text file of
+/// `weight<TAB>path` lines as listed by `fastjump edit` or autojump.
+///
+/// The weights of a database are converted to the weighting, the ones of a
+/// text file are taken as they are.
+pub fn load_source(bytes: &[u8], weighting: Weighting) -> Result<Vec<SourceEntry>> {
+    let mut errors = vec![];
+    if !bytes.starts_with(MAGIC) {
+        match parse(bytes) {
+            Ok(entries) => return Ok(entries.into_iter().map(|(p, w)| (p, w, None)).collect()),
+            Err(e) => errors = e,
+        }
+    }
+    // the databases of the first version have no header
+    let data = Database::from_bytes(bytes).map_err(|e| match errors.first() {
+        Some(line) => anyhow!("neither a database nor a text file of entries, {}", line),
+        None => e,
+    })?;
+    Ok(data
+        .iter()
+        .map(|(path, weight)| {
+            let weight = data.weighting().convert(*weight, weighting);
+            (path.clone(), weight, data.visited(path))
+        })
+        .collect())
+}
+
+/// An entry added or changed by merging.
+#[derive(Debug, PartialEq)]
+pub struct MergeChange {
+    pub path: PathBuf,
+    /// The weight before merging, if the entry existed
+    pub old: Option<f32>,
+    pub new: f32,
+    pub visited: Option<u64>,
+}
+
+/// Computes the entries which merging the other ones into the database adds
+/// or changes. The entries only in the other database are added as they are.
+/// The other ones of the same path after remapping are merged into one first,
+/// as if all of the visits had been made to it, and then combined with the
+/// entry of the database once.
+///
+/// With `MergeStrategy::Average`, the other weights count for `ratio` and the
+/// ones of the database for the rest.
+pub fn merge_changes(
+    data: &Database,
+    entries: Vec<SourceEntry>,
+    maps: &[PathMap],
+    strategy: MergeStrategy,
+    ratio: f32,
+) -> Vec<MergeChange> {
+    let combine = |old: f32, new: f32| match strategy {
+        MergeStrategy::Max => old.max(new),
+        MergeStrategy::Sum => data.weighting().merge(old, new),
+        MergeStrategy::Average => old * (1.0 - ratio) + new * ratio,
+    };
+    let mut merged: HashMap<PathBuf, (f32, Option<u64>)> = HashMap::with_capacity(entries.len());
+    for (path, weight, visited) in entries {
+        match merged.entry(remap(&path, maps)) {
+            // several entries can be mapped to the same path
+            Entry::Occupied(mut x) => {
+                let entry = x.get_mut();
+                entry.0 = data.weighting().merge(entry.0, weight);
+                entry.1 = entry.1.max(visited);
+            }
+            Entry::Vacant(x) => {
+                x.insert((weight, visited));
+            }
+        }
+    }
+    let mut changes: Vec<_> = merged
+        .into_iter()
+        .map(|(path, (weight, visited))| {
+            let old = data.get(&path).copied();
+            MergeChange {
+                new: old.map_or(weight, |old| combine(old, weight)),
+                visited: data.visited(&path).max(visited),
+                path,
+                old,
+            }
+        })
+        .filter(|x| x.old != Some(x.new) || x.visited != data.visited(&x.path))
+        .collect();
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_remap() {
+        let maps: Vec<PathMap> = ["/home/a=/Users/a", "/home/a/src=/src"]
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        assert_eq!(
+            remap(Path::new("/home/a/x"), &maps),
+            Path::new("/Users/a/x")
+        );
+        assert_eq!(
+            remap(Path::new("/home/a/src/x"), &maps),
+            Path::new("/src/x")
+        );
+        assert_eq!(remap(Path::new("/home/a"), &maps), Path::new("/Users/a"));
+        assert_eq!(remap(Path::new("/home/ab"), &maps), Path::new("/home/ab"));
+        assert!("/a".parse::<PathMap>().is_err());
+    }
+
+    #[test]
+    fn test_merge_changes() {
+        let mut data = Database::from(HashMap::new());
+        data.reweight(Weighting::Linear);
+        data.insert(PathBuf::from("/a"), 4.0);
+        data.insert(PathBuf::from("/b"), 1.0);
+        let entries = || {
+            vec![
+                (PathBuf::from("/old/a"), 2.0, None),
+                (PathBuf::from("/b"), 1.0, None),
+                (PathBuf::from("/c"), 3.0, Some(42)),
+            ]
+        };
+        let maps = ["/old=/".parse().unwrap()];
+
+        let changes = merge_changes(&data, entries(), &maps, MergeStrategy::Max, 0.5);
+        assert_eq!(
+            changes,
+            [MergeChange {
+                path: PathBuf::from("/c"),
+                old: None,
+                new: 3.0,
+                visited: Some(42),
+            }]
+        );
+
+        let changes = merge_changes(&data, entries(), &maps, MergeStrategy::Sum, 0.5);
+        let weights: Vec<_> = changes.iter().map(|x| (x.old, x.new)).collect();
+        assert_eq!(weights, [(Some(4.0), 6.0), (Some(1.0), 2.0), (None, 3.0)]);
+
+        let changes = merge_changes(&data, entries(), &maps, MergeStrategy::Average, 0.25);
+        assert_eq!(changes[0].new, 3.5);
+    }
+
+    #[test]
+    fn test_merge_changes_same_path() {
+        let mut data = Database::from(HashMap::new());
+        data.reweight(Weighting::Linear);
+        data.insert(PathBuf::from("/a"), 4.0);
+        let maps = ["/x=/a".parse().unwrap(), "/y=/a".parse().unwrap()];
+        let entries = [
+            (PathBuf::from("/x"), 2.0, None),
+            (PathBuf::from("/y"), 6.0, Some(42)),
+        ];
+
+        // the other entries are merged before they are averaged with the entry
+        for order in [entries.to_vec(), entries.iter().rev().cloned().collect()] {
+            let changes = merge_changes(&data, order, &maps, MergeStrategy::Average, 0.5);
+            assert_eq!(
+                changes,
+                [MergeChange {
+                    path: PathBuf::from("/a"),
+                    old: Some(4.0),
+                    new: 6.0,
+                    visited: Some(42),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_source() {
+        let entries = load_source(b"10\t/a\n2.5\t/b\n", Weighting::Linear).unwrap();
+        assert_eq!(
+            entries,
+            [
+                (PathBuf::from("/a"), 10.0, None),
+                (PathBuf::from("/b"), 2.5, None)
+            ]
+        );
+
+        let mut data = Database::from(HashMap::new());
+        data.insert(PathBuf::from("/a"), 10.0);
+        data.set_visited(Path::new("/a"), Some(42));
+        let entries = load_source(&data.to_bytes().unwrap(), Weighting::Linear).unwrap();
+        assert_eq!(entries, [(PathBuf::from("/a"), 1.0, Some(42))]);
+
+        assert!(load_source(b"\x01\x02", Weighting::Linear).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::common::exists::{first_existing_by, CHECK_TIMEOUT};
+use crate::common::utils::path_from_bytes;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::read;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// What has become of the path of an entry.
+#[derive(Debug, PartialEq)]
+pub enum PathState {
+    /// It's an existing directory
+    Directory,
+    /// It doesn't exist
+    Missing,
+    /// It's something other than a directory
+    NotADirectory,
+    /// It can't be checked for lack of permission
+    PermissionDenied,
+    /// It lives under the mount point, which is not mounted at the moment
+    Unmounted(PathBuf),
+    /// It can't be checked for another reason, eg. a disconnected sshfs
+    Unreachable(String),
+}
+
+impl PathState {
+    /// Checks if the entry should be purged.
+    pub fn is_gone(&self) -> bool {
+        matches!(self, PathState::Missing | PathState::NotADirectory)
+    }
+}
+
+impl fmt::Display for PathState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathState::Directory => write!(f, "directory"),
+            PathState::Missing => write!(f, "missing"),
+            PathState::NotADirectory => write!(f, "not a directory"),
+            PathState::PermissionDenied => write!(f, "permission denied"),
+            PathState::Unmounted(point) => write!(f, "{} not mounted", point.display()),
+            PathState::Unreachable(e) => write!(f, "unreachable: {}", e),
+        }
+    }
+}
+
+/// Decodes the `\ooo` octal escapes of the spaces and the like in the paths
+/// of `/proc/self/mountinfo` and `/etc/fstab`.
+fn unescape_octal(field: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(field.len());
+    let mut i = 0;
+    while i < field.len() {
+        let octal = field
+            .get(i + 1..i + 4)
+            .filter(|x| field[i] == b'\\' && x.iter().all(|c| (b'0'..=b'7').contains(c)));
+        match octal {
+            Some(x) => {
+                ret.push(x.iter().fold(0u8, |n, c| n.wrapping_mul(8) + (c - b'0')));
+                i += 4;
+            }
+            None => {
+                ret.push(field[i]);
+                i += 1;
+            }
+        }
+    }
+    ret
+}
+
+/// Parses the mount points of `/proc/self/mountinfo`, the fifth field of
+/// every line.
+fn parse_mountinfo(text: &[u8]) -> HashSet<PathBuf> {
+    text.split(|x| *x == b'\n')
+        .filter_map(|line| line.split(|x| *x == b' ').nth(4))
+        .filter_map(|x| path_from_bytes(unescape_octal(x)))
+        .collect()
+}
+
+/// Parses the mount points of `/etc/fstab`, the second field of every line
+/// except the ones of the root and swap.
+fn parse_fstab(text: &[u8]) -> Vec<PathBuf> {
+    text.split(|x| *x == b'\n')
+        .filter(|line| !line.trim_ascii_start().starts_with(b"#"))
+        .filter_map(|line| {
+            line.split(|x| x.is_ascii_whitespace())
+                .filter(|x| !x.is_empty())
+                .nth(1)
+        })
+        .filter_map(|x| path_from_bytes(unescape_octal(x)))
+        .filter(|x| x.is_absolute() && x != Path::new("/"))
+        .collect()
+}
+
+/// Reads the current mount points, if the system lists them.
+fn read_mountinfo() -> Option<HashSet<PathBuf>> {
+    read("/proc/self/mountinfo")
+        .ok()
+        .map(|x| parse_mountinfo(&x))
+}
+
+/// Returns the mount point of the file system the path is on, unless it's
+/// the one of the root, by comparing the devices of the ancestors of its
+/// canonical path.
+///
+/// Where the mount points are listed, the one found must be among them, as
+/// the device also changes eg. on the subvolumes of btrfs, which are never
+/// reported as unmounted.
+#[cfg(target_family = "unix")]
+pub fn mount_point(path: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+    let device = |path: &Path| path.metadata().map(|x| x.dev()).ok();
+    let path = path.canonicalize().ok()?;
+    let dev = device(&path)?;
+    if device(Path::new("/")) == Some(dev) {
+        return None;
+    }
+    let mut point = path.as_path();
+    while let Some(parent) = point.parent() {
+        if device(parent) != Some(dev) {
+            break;
+        }
+        point = parent;
+    }
+    match read_mountinfo() {
+        Some(mounted) if !mounted.contains(point) => None,
+        _ => Some(point.to_path_buf()),
+    }
+}
+
+#[cfg(not(target_family = "unix"))]
+pub fn mount_point(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// The file systems which are mounted, and the ones which are expected to be.
+#[derive(Debug, Default)]
+pub struct Mounts {
+    /// The current mount points, if they are known
+    mounted: Option<HashSet<PathBuf>>,
+    /// The mount points listed in fstab, and the ones the entries were added
+    /// on, see `Database::record_mount`
+    known: Vec<PathBuf>,
+}
+
+impl Mounts {
+    /// Reads the mount points of the system, where it can, along with the
+    /// ones recorded by the database.
+    pub fn load<'a>(recorded: impl IntoIterator<Item = &'a PathBuf>) -> Mounts {
+        let mut known = read("/etc/fstab")
+            .map(|x| parse_fstab(&x))
+            .unwrap_or_default();
+        known.extend(recorded.into_iter().cloned());
+        Mounts {
+            mounted: read_mountinfo(),
+            known,
+        }
+    }
+
+    /// Checks if a file system is mounted at the directory, falling back to
+    /// compare its device with the one of its parent if the mount points are
+    /// unknown.
+    fn is_mounted(&self, point: &Path) -> bool {
+        if let Some(mounted) = &self.mounted {
+            return mounted.contains(point);
+        }
+        #[cfg(target_family = "unix")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let device = |path: &Path| path.metadata().map(|x| x.dev()).ok();
+            match (device(point), point.parent().and_then(device)) {
+                (Some(dev), Some(parent)) => dev != parent,
+                _ => false,
+            }
+        }
+        #[cfg(not(target_family = "unix"))]
+        false
+    }
+
+    /// Returns the known mount point the path lives under if nothing is
+    /// mounted there at the moment, eg. a removable disk which is unplugged.
+    pub fn absent_mount(&self, path: &Path) -> Option<PathBuf> {
+        self.known
+            .iter()
+            .filter(|x| path.starts_with(x))
+            .max_by_key(|x| x.components().count())
+            .filter(|x| !self.is_mounted(x))
+            .cloned()
+    }
+
+    /// Checks if the path is gone for sure, i.e. it's not found, not even as a
+    /// dangling symlink, and the file system it was on is mounted. A path
+    /// which can't be accessed, eg. for lack of permission or on a stale NFS
+    /// handle, is not.
+    pub fn is_removed(&self, path: &Path) -> bool {
+        matches!(path.symlink_metadata(), Err(e) if e.kind() == ErrorKind::NotFound)
+            && self.check(path) == PathState::Missing
+    }
+
+    /// Checks what has become of the path.
+    pub fn check(&self, path: &Path) -> PathState {
+        match path.metadata() {
+            Ok(x) if x.is_dir() => PathState::Directory,
+            Ok(_) => PathState::NotADirectory,
+            Err(e) => match e.kind() {
+                ErrorKind::NotFound => match self.absent_mount(path) {
+                    Some(point) => PathState::Unmounted(point),
+                    None => PathState::Missing,
+                },
+                // a path component is a file
+                ErrorKind::NotADirectory => PathState::NotADirectory,
+                ErrorKind::PermissionDenied => PathState::PermissionDenied,
+                _ => PathState::Unreachable(e.to_string()),
+            },
+        }
+    }
+
+    /// Checks what has become of the paths, in parallel and giving up on the
+    /// ones which don't answer in time, eg. on a hung NFS mount.
+    pub fn check_all<'a>(&self, paths: &[&'a Path]) -> Vec<(&'a Path, PathState)> {
+        let (dirs, others) = first_existing_by(
+            paths.iter().copied(),
+            usize::MAX,
+            CHECK_TIMEOUT,
+            Path::is_dir,
+        );
+        let dirs: HashSet<_> = dirs.into_iter().collect();
+        let others: HashSet<_> = others.into_iter().collect();
+        paths
+            .iter()
+            .map(|path| {
+                let state = if dirs.contains(path) {
+                    PathState::Directory
+                } else if others.contains(path) {
+                    // it has answered just now
+                    self.check(path)
+                } else {
+                    PathState::Unreachable("timed out".to_string())
+                };
+                (*path, state)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mounts() {
+        let mountinfo = b"23 28 0:22 / /proc rw,relatime - proc proc rw\n\
+            40 28 8:17 / /media/usb\\040disk rw - vfat /dev/sdb1 rw\n";
+        let mounted = parse_mountinfo(mountinfo);
+        assert!(mounted.contains(Path::new("/proc")));
+        assert!(mounted.contains(Path::new("/media/usb disk")));
+        assert_eq!(mounted.len(), 2);
+
+        let fstab = b"# <file system> <mount point> <type>\n\
+            UUID=1 / ext4 defaults 0 1\n\
+            UUID=2 none swap sw 0 0\n\
+            \n\
+            server:/srv  /net/srv\\040x  nfs  noauto  0 0\n";
+        assert_eq!(parse_fstab(fstab), [PathBuf::from("/net/srv x")]);
+    }
+
+    #[test]
+    fn test_absent_mount() {
+        let mounts = Mounts {
+            mounted: Some(
+                ["/", "/media/user/disk", "/net/a"]
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            ),
+            known: ["/net/a", "/net/b", "/media/user/disk", "/media/user/usb"]
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        };
+        let absent = |x: &str| mounts.absent_mount(Path::new(x));
+        assert_eq!(absent("/net/a/foo"), None);
+        assert_eq!(absent("/net/b/foo"), Some(PathBuf::from("/net/b")));
+        assert_eq!(absent("/media/user/disk/foo"), None);
+        assert_eq!(
+            absent("/media/user/usb/foo"),
+            Some(PathBuf::from("/media/user/usb"))
+        );
+        // not known to be a mount point, it's simply gone
+        assert_eq!(absent("/media/user/other/foo"), None);
+        assert_eq!(absent("/home/user"), None);
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_mount_point() {
+        assert_eq!(mount_point(Path::new("/")), None);
+        let dir = tempfile::tempdir().unwrap();
+        if let Some(point) = mount_point(dir.path()) {
+            assert!(dir.path().canonicalize().unwrap().starts_with(&point));
+            assert!(read_mountinfo().is_none_or(|x| x.contains(&point)));
+        }
+
+        // reached through a symlink from another file system
+        let shm = Path::new("/dev/shm");
+        if !read_mountinfo().is_some_and(|x| x.contains(shm)) {
+            return;
+        }
+        let mounted = tempfile::tempdir_in(shm).unwrap();
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(mounted.path(), &link).unwrap();
+        assert_eq!(mount_point(&link), Some(shm.to_path_buf()));
+        assert_eq!(mount_point(mounted.path()), Some(shm.to_path_buf()));
+    }
+
+    #[test]
+    fn test_check() {
+        let mounts = Mounts::default();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+
+        assert_eq!(mounts.check(dir.path()), PathState::Directory);
+        assert_eq!(mounts.check(&file), PathState::NotADirectory);
+        assert_eq!(mounts.check(&file.join("foo")), PathState::NotADirectory);
+        assert_eq!(mounts.check(&dir.path().join("foo")), PathState::Missing);
+        let missing = dir.path().join("foo");
+        assert_eq!(
+            mounts.check_all(&[dir.path(), &file, &missing]),
+            [
+                (dir.path(), PathState::Directory),
+                (file.as_path(), PathState::NotADirectory),
+                (missing.as_path(), PathState::Missing),
+            ]
+        );
+        assert!(mounts.is_removed(&missing));
+        assert!(!mounts.is_removed(&file.join("foo")));
+        assert!(PathState::Missing.is_gone());
+        assert!(!PathState::PermissionDenied.is_gone());
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_not_removed() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let mounts = Mounts::default();
+        let dir = tempfile::tempdir().unwrap();
+        let link = dir.path().join("link");
+        symlink(dir.path().join("nowhere"), &link).unwrap();
+        assert!(!mounts.is_removed(&link));
+
+        // root can still access it, which is not removed either
+        let locked = dir.path().join("locked");
+        let inner = locked.join("inner");
+        std::fs::create_dir_all(&inner).unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        let removed = mounts.is_removed(&inner);
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(!removed);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::common::output::with_path;
+use crate::common::r#match::glob_match;
+use crate::common::utils::{abbreviate_home, expand_home, normalize_path, CWD};
+use crate::database::Database;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+pub const STATS_SORTS: &[&str] = &["weight", "path", "visited"];
+pub const STATS_FORMATS: &[&str] = &["text", "json", "tsv"];
+
+/// How the entries of the statistics are ordered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsSort {
+    /// The heaviest first
+    Weight,
+    /// Alphabetically
+    Path,
+    /// The most recently visited first
+    Visited,
+}
+
+impl FromStr for StatsSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "weight" => Ok(StatsSort::Weight),
+            "path" => Ok(StatsSort::Path),
+            "visited" => Ok(StatsSort::Visited),
+            _ => Err(format!("unknown sort order {}", s)),
+        }
+    }
+}
+
+/// The output format of the statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatsFormat {
+    /// A table for humans
+    Text,
+    /// A JSON object for scripts
+    Json,
+    /// A `weight<TAB>visited<TAB>entries<TAB>path` line per entry for scripts
+    Tsv,
+}
+
+impl FromStr for StatsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(StatsFormat::Text),
+            "json" => Ok(StatsFormat::Json),
+            "tsv" => Ok(StatsFormat::Tsv),
+            _ => Err(format!("unknown stats format {}", s)),
+        }
+    }
+}
+
+/// Which entries the statistics report and how.
+#[derive(Debug, Clone)]
+pub struct StatsOptions {
+    /// Report this many entries at most
+    pub top: Option<usize>,
+    /// Only the entries matching the glob pattern
+    pub pattern: Option<PathBuf>,
+    /// Only the entries under the path
+    pub prefix: Option<PathBuf>,
+    pub sort: StatsSort,
+    pub reverse: bool,
+    /// Aggregate the entries per top-level directory
+    pub group: bool,
+    /// Abbreviate the home directory to `~` in the text
+    pub abbreviate: bool,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        StatsOptions {
+            top: None,
+            pattern: None,
+            prefix: None,
+            sort: StatsSort::Weight,
+            reverse: false,
+            group: false,
+            abbreviate: true,
+        }
+    }
+}
+
+/// An entry of the statistics, or a group of entries.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StatsItem {
+    /// Added to the JSON by `format_json`
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub weight: f32,
+    /// When the entry, or the latest one of the group, was visited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visited: Option<u64>,
+    /// How many entries are in the group
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entries: Option<usize>,
+}
+
+/// The statistics of the database.
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    /// Added to the JSON by `format_json`
+    #[serde(skip)]
+    pub database: PathBuf,
+    /// The size of the database file in bytes
+    pub size: u64,
+    pub weighting: String,
+    /// How many entries are in the database
+    pub entries: usize,
+    pub total_weight: f32,
+    /// How many entries are matched by the filters, before `top` is applied
+    pub matched: usize,
+    pub matched_weight: f32,
+    /// The weight of the current directory
+    pub current_weight: f32,
+    /// Added to the JSON by `format_json`
+    #[serde(skip)]
+    pub items: Vec<StatsItem>,
+}
+
+/// Returns the top-level directory of a path, eg. `/usr` of `/usr/bin`, or
+/// `~/src` of `~/src/fastjump`.
+fn top_level(path: &Path) -> PathBuf {
+    let mut top = PathBuf::new();
+    for component in path.components() {
+        top.push(component);
+        if let Component::Normal(x) = component {
+            if x != "~" || top.components().count() > 1 {
+                break;
+            }
+        }
+    }
+    top
+}
+
+/// Collects the statistics of the entries selected by the options.
+pub fn stats_report(data: &Database, data_path: &Path, options: &StatsOptions) -> StatsReport {
+    let pattern = options.pattern.as_ref().map(|x| expand_home(x));
+    let prefix = options.prefix.as_ref().map(|x| expand_home(x));
+    let display = |path: &Path| {
+        if options.abbreviate {
+            abbreviate_home(path)
+        } else {
+            path.to_path_buf()
+        }
+    };
+
+    let matched: Vec<_> = data
+        .iter()
+        .filter(|(path, _)| match &pattern {
+            Some(pattern) => glob_match(
+                pattern.as_os_str().as_encoded_bytes(),
+                path.as_os_str().as_encoded_bytes(),
+            ),
+            None => true,
+        })
+        .filter(|(path, _)| match &prefix {
+            Some(prefix) => path.starts_with(prefix),
+            None => true,
+        })
+        .collect();
+
+    let mut items: Vec<StatsItem> = if options.group {
+        let mut groups: HashMap<PathBuf, StatsItem> = HashMap::new();
+        for (path, weight) in &matched {
+            let top = top_level(&display(path));
+            let item = groups.entry(top.clone()).or_insert_with(|| StatsItem {
+                path: top.clone(),
+                weight: 0.0,
+                visited: None,
+                entries: Some(0),
+            });
+            item.weight += **weight;
+            item.visited = item.visited.max(data.visited(path));
+            item.entries = item.entries.map(|x| x + 1);
+        }
+        groups.into_values().collect()
+    } else {
+        matched
+            .iter()
+            .map(|(path, weight)| StatsItem {
+                path: display(path),
+                weight: **weight,
+                visited: data.visited(path),
+                entries: None,
+            })
+            .collect()
+    };
+
+    items.sort_by(|a, b| {
+        let order = match options.sort {
+            StatsSort::Weight => b.weight.partial_cmp(&a.weight).unwrap_or(Ordering::Equal),
+            StatsSort::Path => Ordering::Equal,
+            StatsSort::Visited => b.visited.cmp(&a.visited),
+        };
+        order.then_with(|| a.path.cmp(&b.path))
+    });
+    if options.reverse {
+        items.reverse();
+    }
+    if let Some(top) = options.top {
+        items.truncate(top);
+    }
+
+    StatsReport {
+        database: data_path.to_path_buf(),
+        size: data_path.metadata().map(|x| x.len()).unwrap_or(0),
+        weighting: data.weighting().to_string(),
+        entries: data.len(),
+        total_weight: data.values().sum(),
+        matched: matched.len(),
+        matched_weight: matched.iter().map(|x| x.1).sum(),
+        current_weight: *data.get(&normalize_path(&CWD)).unwrap_or(&0.0),
+        items,
+    }
+}
+
+/// Shortens the text to the width by eliding its start, as the end of a path
+/// tells more about it.
+fn truncate_start(text: &str, width: usize) -> String {
+    let count = text.chars().count();
+    if count <= width || width == 0 {
+        return text.to_string();
+    }
+    let mut ret = String::from("…");
+    ret.extend(text.chars().skip(count - width + 1));
+    ret
+}
+
+/// Formats the statistics as JSON, with the paths as `with_path` does.
+pub fn format_json(report: &StatsReport) -> serde_json::Result<Value> {
+    let items = report
+        .items
+        .iter()
+        .map(|x| Ok(with_path(serde_json::to_value(x)?, "path", &x.path)))
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    let mut ret = with_path(serde_json::to_value(report)?, "database", &report.database);
+    ret["items"] = Value::Array(items);
+    Ok(ret)
+}
+
+/// Returns the fields of an entry of the statistics before its path in the
+/// tab-separated lines, see `print_tsv`: the weight, the last visit and the
+/// size of the group, the unknown ones left empty.
+pub fn tsv_fields(item: &StatsItem) -> Vec<String> {
+    let optional = |x: Option<u64>| x.map(|x| x.to_string()).unwrap_or_default();
+    vec![
+        item.weight.to_string(),
+        optional(item.visited),
+        optional(item.entries.map(|x| x as u64)),
+    ]
+}
+
+/// Formats the statistics as a table, truncating the paths to fit in the
+/// width if it's given.
+pub fn format_text(report: &StatsReport, width: Option<usize>) -> String {
+    let grouped = report.items.iter().any(|x| x.entries.is_some());
+    let prefix_width = if grouped { 20 } else { 12 };
+    let fit = |path: &Path| match width {
+        Some(width) => truncate_start(&path.to_string_lossy(), width.saturating_sub(prefix_width)),
+        None => path.to_string_lossy().into_owned(),
+    };
+
+    let mut out = String::new();
+    for item in &report.items {
+        match item.entries {
+            Some(entries) => writeln!(
+                out,
+                "{:>10.2}  {:>6}  {}",
+                item.weight,
+                entries,
+                fit(&item.path)
+            ),
+            None => writeln!(out, "{:>10.2}  {}", item.weight, fit(&item.path)),
+        }
+        .unwrap();
+    }
+
+    writeln!(out, "{}", "_".repeat(width.unwrap_or(80).min(80))).unwrap();
+    writeln!(
+        out,
+        "{:>10} / {} entries shown",
+        report.items.len(),
+        report.matched
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "{:>10.2} / {:.2} matched weight",
+        report.matched_weight, report.total_weight
+    )
+    .unwrap();
+    writeln!(out, "{:>10} entries in total", report.entries).unwrap();
+    writeln!(
+        out,
+        "{:>10.2} current directory weight",
+        report.current_weight
+    )
+    .unwrap();
+    writeln!(out, "weighting:\t{}", report.weighting).unwrap();
+    writeln!(
+        out,
+        "database file:\t{} ({} bytes)",
+        report.database.display(),
+        report.size
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Database {
+        let mut data = Database::from(HashMap::new());
+        data.insert(PathBuf::from("/usr/bin"), 10.0);
+        data.insert(PathBuf::from("/usr/lib"), 20.0);
+        data.insert(PathBuf::from("/tmp"), 5.0);
+        data.insert(PathBuf::from("/tmp/foo"), 30.0);
+        data.set_visited(Path::new("/tmp"), Some(42));
+        data
+    }
+
+    fn paths(report: &StatsReport) -> Vec<&str> {
+        report
+            .items
+            .iter()
+            .map(|x| x.path.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_stats_report() {
+        let data = database();
+        let mut options = StatsOptions {
+            abbreviate: false,
+            ..Default::default()
+        };
+        let report = stats_report(&data, Path::new("/nonexistent"), &options);
+        assert_eq!(paths(&report), ["/tmp/foo", "/usr/lib", "/usr/bin", "/tmp"]);
+        assert_eq!(report.total_weight, 65.0);
+
+        options.top = Some(1);
+        options.prefix = Some(PathBuf::from("/usr"));
+        let report = stats_report(&data, Path::new("/nonexistent"), &options);
+        assert_eq!(paths(&report), ["/usr/lib"]);
+        assert_eq!((report.matched, report.matched_weight), (2, 30.0));
+
+        options = StatsOptions {
+            pattern: Some(PathBuf::from("/*/b*")),
+            sort: StatsSort::Path,
+            abbreviate: false,
+            ..Default::default()
+        };
+        let report = stats_report(&data, Path::new("/nonexistent"), &options);
+        assert_eq!(paths(&report), ["/usr/bin"]);
+
+        options = StatsOptions {
+            sort: StatsSort::Visited,
+            group: true,
+            reverse: true,
+            abbreviate: false,
+            ..Default::default()
+        };
+        let report = stats_report(&data, Path::new("/nonexistent"), &options);
+        assert_eq!(
+            report.items,
+            [
+                StatsItem {
+                    path: PathBuf::from("/usr"),
+                    weight: 30.0,
+                    visited: None,
+                    entries: Some(2),
+                },
+                StatsItem {
+                    path: PathBuf::from("/tmp"),
+                    weight: 35.0,
+                    visited: Some(42),
+                    entries: Some(2),
+                },
+            ]
+        );
+        assert_eq!(tsv_fields(&report.items[1]), ["35", "42", "2"]);
+
+        let json = format_json(&report).unwrap();
+        assert_eq!(json["database"], "/nonexistent");
+        assert_eq!(json["items"][1]["path"], "/tmp");
+        assert_eq!(json["items"][1]["entries"], 2);
+    }
+
+    #[test]
+    fn test_top_level() {
+        assert_eq!(top_level(Path::new("/usr/bin")), Path::new("/usr"));
+        assert_eq!(top_level(Path::new("/")), Path::new("/"));
+        assert_eq!(top_level(Path::new("~/src/foo")), Path::new("~/src"));
+        assert_eq!(top_level(Path::new("~")), Path::new("~"));
+    }
+
+    #[test]
+    fn test_truncate_start() {
+        assert_eq!(truncate_start("/usr/bin", 8), "/usr/bin");
+        assert_eq!(truncate_start("/usr/bin", 5), "…/bin");
+        assert_eq!(truncate_start("/usr/bin", 0), "/usr/bin");
+    }
+}
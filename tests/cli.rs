@@ -0,0 +1,416 @@
+//! Runs the binary as the shell integration does, each test with a database of
+//! its own in a temporary directory.
+use assert_cmd::Command;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Sandbox {
+        let dir = tempfile::tempdir().unwrap();
+        create_dir_all(dir.path().join("home")).unwrap();
+        Sandbox { dir }
+    }
+
+    /// Returns a directory in the sandbox, created if it doesn't exist.
+    fn mkdir(&self, name: &str) -> PathBuf {
+        let path = self.dir.path().join(name);
+        create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn data_dir(&self) -> PathBuf {
+        self.dir.path().join("data")
+    }
+
+    fn fastjump(&self) -> Command {
+        Command::from_std(self.process())
+    }
+
+    /// Returns the command of `fastjump`, to be spawned in the background.
+    fn process(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(assert_cmd::cargo::cargo_bin("fastjump"));
+        for (name, _) in std::env::vars_os() {
+            if name.to_string_lossy().starts_with("FASTJUMP_") {
+                cmd.env_remove(name);
+            }
+        }
+        cmd.env("FASTJUMP_SOURCED", "1")
+            .env("FASTJUMP_DATA_DIR", self.data_dir())
+            .env("HOME", self.dir.path().join("home"))
+            .current_dir(self.dir.path());
+        cmd
+    }
+
+    /// Runs fastjump with the arguments and returns its stdout.
+    fn run(&self, args: &[&str]) -> String {
+        let output = self.fastjump().args(args).assert().success();
+        String::from_utf8(output.get_output().stdout.clone()).unwrap()
+    }
+
+    fn add(&self, path: &Path, weight: &str) {
+        self.fastjump()
+            .args(["-i", weight])
+            .current_dir(path)
+            .assert()
+            .success();
+    }
+}
+
+#[test]
+fn complete_index_without_cache() {
+    let sandbox = Sandbox::new();
+    let gone = sandbox.mkdir("a/foo");
+    let kept = sandbox.mkdir("b/foo");
+    sandbox.add(&gone, "50");
+    sandbox.add(&kept, "10");
+    std::fs::remove_dir(&gone).unwrap();
+
+    let menu = sandbox.run(&["--complete", "foo"]);
+    assert_eq!(
+        menu,
+        format!("foo__1__{}\nfoo__2__{}\n", gone.display(), kept.display())
+    );
+    // the index resolves to the entry offered, even if the menu is forgotten
+    assert_eq!(sandbox.run(&["foo__1"]), format!("{}\n", gone.display()));
+    std::fs::remove_file(sandbox.data_dir().join("fastjump.completion")).unwrap();
+    assert_eq!(sandbox.run(&["foo__1"]), format!("{}\n", gone.display()));
+
+    // an index which was never offered is no match, with the menu or without
+    for index in ["foo__0", "foo__3"] {
+        assert_eq!(sandbox.run(&[index]), ".\n");
+        sandbox.run(&["--complete", "foo"]);
+        assert_eq!(sandbox.run(&[index]), ".\n");
+        std::fs::remove_file(sandbox.data_dir().join("fastjump.completion")).unwrap();
+    }
+}
+
+#[test]
+fn command_dispatch() {
+    let sandbox = Sandbox::new();
+    let init = sandbox.run(&["--profile", "work", "init", "bash"]);
+    assert!(init.contains("__fastjump_complete"));
+    assert!(sandbox.run(&["init", "tcsh"]).contains("complete j "));
+
+    // a directory named like a command is jumped to after `--`
+    let stats = sandbox.mkdir("stats");
+    sandbox.add(&stats, "10");
+    let home = sandbox.mkdir("home");
+    let jump = |args: &[&str]| {
+        let output = sandbox.fastjump().args(args).current_dir(&home).assert();
+        String::from_utf8(output.success().get_output().stdout.clone()).unwrap()
+    };
+    assert_eq!(jump(&["--", "stats"]), format!("{}\n", stats.display()));
+    assert!(jump(&["stats"]).contains("entries in total"));
+}
+
+#[test]
+fn import_history() {
+    let sandbox = Sandbox::new();
+    let alpha = sandbox.mkdir("proj/alpha");
+    let beta = sandbox.mkdir("proj/beta");
+    let history = sandbox.dir.path().join("history");
+    std::fs::write(
+        &history,
+        format!(
+            "cd {}\ncd ~\ncd {}\ncd {}\n",
+            alpha.display(),
+            beta.display(),
+            alpha.display()
+        ),
+    )
+    .unwrap();
+
+    // a single summary, the home directory is not recorded
+    let history = history.to_str().unwrap();
+    let summary = sandbox.run(&["--output", "json", "import", "bash", history]);
+    assert_eq!(summary.lines().count(), 1);
+    assert!(summary.contains("\"imported\":3"));
+    assert_eq!(
+        sandbox.run(&["--", "alph"]),
+        format!("{}\n", alpha.display())
+    );
+    assert!(sandbox.run(&["stats"]).contains(" 2 entries in total"));
+}
+
+#[test]
+fn malformed_weighting() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.mkdir("dir");
+    let output = sandbox
+        .fastjump()
+        .env("FASTJUMP_WEIGHTING", "Linear")
+        .args(["-i", "5"])
+        .current_dir(&dir)
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&output.get_output().stderr);
+    assert!(stderr.contains("unknown weighting Linear"), "{}", stderr);
+    assert!(!sandbox.data_dir().join("fastjump.db").exists());
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn weight_canonical() {
+    let sandbox = Sandbox::new();
+    let real = sandbox.mkdir("real");
+    let link = sandbox.dir.path().join("link");
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+
+    // the target is recorded as the entries are, with the symlinks resolved
+    sandbox
+        .fastjump()
+        .env("FASTJUMP_CANONICAL", "true")
+        .args(["weight", "set", "42", "link"])
+        .assert()
+        .success();
+    let real = real.canonicalize().unwrap();
+    let stats = sandbox.run(&["stats"]);
+    assert!(
+        stats.contains(&format!("42.00  {}\n", real.display())),
+        "{}",
+        stats
+    );
+    assert!(!stats.contains("link"));
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn move_entries() {
+    let sandbox = Sandbox::new();
+    let old = sandbox.mkdir("old/alpha");
+    sandbox.add(&old, "10");
+    let new = sandbox.dir.path().join("new");
+    std::fs::rename(sandbox.dir.path().join("old"), &new).unwrap();
+    std::os::unix::fs::symlink(&new, sandbox.dir.path().join("link")).unwrap();
+
+    sandbox
+        .fastjump()
+        .env("FASTJUMP_CANONICAL", "true")
+        .args(["move", "old", "link"])
+        .assert()
+        .success();
+    let moved = new.canonicalize().unwrap().join("alpha");
+    assert_eq!(
+        sandbox.run(&["--", "alph"]),
+        format!("{}\n", moved.display())
+    );
+    // nothing is left under the old directory
+    sandbox
+        .fastjump()
+        .args(["move", "old", "new"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn purge() {
+    let sandbox = Sandbox::new();
+    let kept = sandbox.mkdir("kept");
+    let gone = sandbox.mkdir("gone");
+    sandbox.add(&kept, "10");
+    sandbox.add(&gone, "10");
+    std::fs::remove_dir(&gone).unwrap();
+
+    let report = sandbox.run(&["--output", "json", "purge", "--dryrun"]);
+    assert!(report.contains("\"removed\":1"), "{}", report);
+    assert!(report.contains("\"dryrun\":true"));
+    assert!(sandbox.run(&["stats"]).contains(" 2 entries in total"));
+
+    let report = sandbox.run(&["--output", "tsv", "purge"]);
+    assert_eq!(report, format!("remove\tmissing\t{}\n", gone.display()));
+    assert!(sandbox.run(&["stats"]).contains(" 1 entries in total"));
+}
+
+#[test]
+fn merge_text() {
+    let sandbox = Sandbox::new();
+    let alpha = sandbox.mkdir("alpha");
+    let beta = sandbox.mkdir("beta");
+    sandbox.add(&alpha, "10");
+    let other = sandbox.dir.path().join("other.txt");
+    std::fs::write(
+        &other,
+        format!(
+            "30\t{}\n2\t/old/beta\n3\t{}\n",
+            alpha.display(),
+            beta.display()
+        ),
+    )
+    .unwrap();
+
+    let map = format!("/old={}", sandbox.dir.path().display());
+    let other = other.to_str().unwrap();
+    sandbox.run(&["merge", other, "--map", &map]);
+    let stats = sandbox.run(&["stats"]);
+    assert!(
+        stats.contains(&format!("30.00  {}\n", alpha.display())),
+        "{}",
+        stats
+    );
+    // the entries mapped to the same path are merged as if one, 2 and 3 visits
+    // of the autojump weighting
+    assert!(
+        stats.contains(&format!("3.61  {}\n", beta.display())),
+        "{}",
+        stats
+    );
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn daemon() {
+    let sandbox = Sandbox::new();
+    let alpha = sandbox.mkdir("proj/alpha");
+    let mut daemon = sandbox.process().arg("daemon").spawn().unwrap();
+    // the socket is bound once the database is loaded
+    let socket = sandbox.data_dir().join("fastjump.sock");
+    for _ in 0..500 {
+        if socket.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    sandbox.add(&alpha, "10");
+    assert_eq!(
+        sandbox.run(&["--", "alph"]),
+        format!("{}\n", alpha.display())
+    );
+    sandbox.run(&["daemon", "--stop"]);
+    assert!(daemon.wait().unwrap().success());
+    assert!(!socket.exists());
+    // the changes are saved on exit
+    assert!(sandbox.run(&["stats"]).contains(" 1 entries in total"));
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn output_json() {
+    use std::os::unix::ffi::OsStrExt;
+    let sandbox = Sandbox::new();
+    let utf8 = sandbox.mkdir("proj/beta");
+    // not valid UTF-8
+    let raw = sandbox
+        .dir
+        .path()
+        .join(std::ffi::OsStr::from_bytes(b"proj/alpha\xff"));
+    create_dir_all(&raw).unwrap();
+    let json = |args: &[&str], cwd: &Path| {
+        let output = sandbox
+            .fastjump()
+            .arg("--output=json")
+            .args(args)
+            .current_dir(cwd)
+            .assert();
+        let stdout = output.success().get_output().stdout.clone();
+        serde_json::from_slice::<serde_json::Value>(&stdout).unwrap()
+    };
+
+    let added = json(&["-i", "10"], &utf8);
+    assert_eq!(added["path"], utf8.to_str().unwrap());
+    assert_eq!(added["weight"], 10.0);
+    assert!(added.get("path_bytes").is_none());
+
+    // the raw bytes come along the lossy path
+    let added = json(&["-i", "20"], &raw);
+    let bytes: Vec<u8> = serde_json::from_value(added["path_bytes"].clone()).unwrap();
+    assert_eq!(bytes, raw.as_os_str().as_bytes());
+    assert_eq!(added["path"], raw.to_string_lossy().as_ref());
+
+    let jump = json(&["--", "alpha"], sandbox.dir.path());
+    assert_eq!(jump["path_bytes"], added["path_bytes"]);
+    assert_eq!(
+        json(&["--", "nothing"], sandbox.dir.path())["path"],
+        serde_json::Value::Null
+    );
+
+    let menu = json(&["--complete", "proj"], sandbox.dir.path());
+    assert_eq!(menu[0]["path_bytes"], added["path_bytes"]);
+    assert_eq!(menu[1]["path"], utf8.to_str().unwrap());
+
+    let stats = json(&["stats", "--pattern", "*alpha*"], sandbox.dir.path());
+    assert_eq!(stats["items"][0]["path_bytes"], added["path_bytes"]);
+    let output = sandbox
+        .fastjump()
+        .args(["--output", "tsv", "stats", "--pattern", "*alpha*"])
+        .assert();
+    // weight, last visit, no group and the path
+    let stdout = output.success().get_output().stdout.clone();
+    let mut end = b"\t\t".to_vec();
+    end.extend_from_slice(raw.as_os_str().as_bytes());
+    end.push(b'\n');
+    assert!(stdout.starts_with(b"20\t") && stdout.ends_with(&end));
+
+    // only ever for a command, never for the shell integration
+    let output = sandbox
+        .fastjump()
+        .env("FASTJUMP_OUTPUT", "json")
+        .args(["--", "beta"])
+        .assert();
+    let stdout = output.success().get_output().stdout.clone();
+    assert_eq!(stdout, format!("{}\n", utf8.display()).into_bytes());
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn dedupe() {
+    let sandbox = Sandbox::new();
+    let real = sandbox.mkdir("real");
+    let link = sandbox.dir.path().join("link");
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+    sandbox.run(&["weight", "set", "10", "real"]);
+    sandbox.run(&["weight", "set", "20", "link"]);
+
+    sandbox.run(&["dedupe", "--dryrun"]);
+    assert!(sandbox.run(&["stats"]).contains(" 2 entries in total"));
+    // the heaviest path is kept
+    sandbox.run(&["dedupe"]);
+    let stats = sandbox.run(&["stats"]);
+    assert!(stats.contains(" 1 entries in total"), "{}", stats);
+    assert!(
+        stats.contains(&format!("  {}\n", link.display())),
+        "{}",
+        stats
+    );
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn edit() {
+    let sandbox = Sandbox::new();
+    let alpha = sandbox.mkdir("proj/alpha");
+    let beta = sandbox.mkdir("proj/beta");
+    sandbox.add(&alpha, "10");
+    sandbox.add(&beta, "20");
+
+    // nothing is changed if the editor fails
+    sandbox
+        .fastjump()
+        .env_remove("VISUAL")
+        .env("EDITOR", "false")
+        .arg("edit")
+        .assert()
+        .failure();
+    assert!(sandbox.run(&["stats"]).contains(" 2 entries in total"));
+
+    // the editor comes with its own arguments
+    sandbox
+        .fastjump()
+        .env_remove("VISUAL")
+        .env("EDITOR", "sed -i -e /beta$/d -e s/^10\\t/42\\t/")
+        .arg("edit")
+        .assert()
+        .success();
+    let stats = sandbox.run(&["stats"]);
+    assert!(stats.contains(" 1 entries in total"), "{}", stats);
+    assert!(
+        stats.contains(&format!("42.00  {}\n", alpha.display())),
+        "{}",
+        stats
+    );
+}